@@ -8,6 +8,8 @@ fn main() {
     println!("cargo:rerun-if-changed=wrapper.h");
     println!("cargo:rerun-if-changed=SimpleMotionV2/simplemotion.h");
     println!("cargo:rerun-if-changed=SimpleMotionV2/simplemotion.c");
+    println!("cargo:rerun-if-changed=SimpleMotionV2/bufferedmotion.h");
+    println!("cargo:rerun-if-changed=SimpleMotionV2/bufferedmotion.c");
 
     // fs::copy("patch/config.h", "linuxcnc-src/src/config.h")
     //     .expect("Failed to copy config patch file");
@@ -22,6 +24,8 @@ fn main() {
         // Tell cargo to invalidate the built crate whenever any of the
         // included header files changed.
         .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
+        // Don't prefix C enum variants with the enum name, e.g. `MiscOperationFlushTX`.
+        .prepend_enum_name(false)
         // Finish the builder and generate the bindings.
         .generate()
         // Unwrap the Result and panic on failure.
@@ -36,6 +40,7 @@ fn main() {
     cc::Build::new()
         .files(&[
             "SimpleMotionV2/simplemotion.c",
+            "SimpleMotionV2/bufferedmotion.c",
             "SimpleMotionV2/sm_consts.c",
             "SimpleMotionV2/busdevice.c",
             "SimpleMotionV2/drivers/serial/pcserialport.c",
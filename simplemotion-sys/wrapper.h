@@ -1,5 +1,6 @@
 #include "SimpleMotionV2/user_options.h"
 #include "SimpleMotionV2/simplemotion.h"
+#include "SimpleMotionV2/bufferedmotion.h"
 
 // NOTE: Redefined from simplemotion_defs.h as the `BV()` macro is not supported by bindgen
 #define FLT_FOLLOWERROR 1<<1
@@ -0,0 +1,396 @@
+//! Extract the `SMP_*`, `STAT_*`, `FLT_*`, etc constants from `simplemotion_defs.h`, and generate
+//! the parameter registry from them.
+//!
+//! This used to come from `simplemotion-sys`, but the `pure-rust` backend must build without
+//! bindgen/libclang or the SimpleMotionV2 submodule, so a copy of the header is vendored in
+//! `vendor/` and parsed here instead. Only object-like macros that evaluate to an integer are
+//! emitted; anything else (strings, function-like macros) is skipped.
+//!
+//! When the submodule is checked out, the build fails if the vendored copy defines anything
+//! differently to it.
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+fn main() {
+    let header =
+        PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("vendor/simplemotion_defs.h");
+
+    println!("cargo:rerun-if-changed={}", header.display());
+
+    let source = fs::read_to_string(&header)
+        .unwrap_or_else(|e| panic!("Could not read {}: {}", header.display(), e));
+
+    let (defines, values) = parse(&source);
+
+    // The vendored copy must not drift from the submodule when both are available
+    let upstream = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap())
+        .join("../simplemotion-sys/SimpleMotionV2/simplemotion_defs.h");
+
+    println!("cargo:rerun-if-changed={}", upstream.display());
+
+    if let Ok(upstream_source) = fs::read_to_string(&upstream) {
+        check_sync(&defines, &parse(&upstream_source).0, &upstream);
+    }
+
+    let mut out = String::new();
+
+    for (name, value) in &defines {
+        let ty = if (0..=i64::from(u32::MAX)).contains(value) {
+            "u32"
+        } else {
+            "i64"
+        };
+
+        out.push_str(&format!("pub const {}: {} = {};\n", name, ty, value));
+    }
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+
+    fs::write(out_dir.join("defs.rs"), out).expect("Couldn't write defs.rs");
+
+    fs::write(out_dir.join("registry.rs"), registry(&defines, &values))
+        .expect("Couldn't write registry.rs");
+}
+
+/// Evaluate every object-like macro in `source` that is an integer constant.
+fn parse(source: &str) -> (Vec<(String, i64)>, HashMap<String, i64>) {
+    let mut values: HashMap<String, i64> = HashMap::new();
+    let mut defines = Vec::new();
+
+    for line in strip_comments(source).lines() {
+        let line = line.trim();
+
+        let Some(rest) = line.strip_prefix("#define") else {
+            continue;
+        };
+
+        let rest = rest.trim_start();
+        let name_len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let (name, expr) = rest.split_at(name_len);
+
+        // Function-like macros (e.g. `BV(bit)`) and empty defines are not constants
+        if name.is_empty() || expr.starts_with('(') || expr.trim().is_empty() {
+            continue;
+        }
+
+        if let Some(value) = Expr::new(expr, &values).parse() {
+            values.insert(name.to_string(), value);
+            defines.push((name.to_string(), value));
+        }
+    }
+
+    (defines, values)
+}
+
+/// Fail the build if the vendored header defines anything differently to the submodule's copy.
+fn check_sync(vendored: &[(String, i64)], upstream: &[(String, i64)], path: &std::path::Path) {
+    let vendored: HashMap<_, _> = vendored.iter().cloned().collect();
+    let upstream: HashMap<_, _> = upstream.iter().cloned().collect();
+
+    let mut differences = Vec::new();
+
+    for (name, value) in &upstream {
+        match vendored.get(name) {
+            Some(v) if v == value => {}
+            Some(v) => differences.push(format!("{} is {} upstream, {} vendored", name, value, v)),
+            None => differences.push(format!("{} is missing", name)),
+        }
+    }
+
+    for name in vendored.keys().filter(|name| !upstream.contains_key(*name)) {
+        differences.push(format!("{} is not defined upstream", name));
+    }
+
+    if !differences.is_empty() {
+        differences.sort();
+
+        panic!(
+            "vendor/simplemotion_defs.h is out of sync with {}. Copy it over verbatim.\n{}",
+            path.display(),
+            differences.join("\n")
+        );
+    }
+}
+
+/// Generate the `parameters!` invocation for `src/registry.rs`.
+///
+/// The typed parameters in `src/registry.in` come first. Every other define that looks like a
+/// parameter address is added as an untyped `i32`.
+fn registry(defines: &[(String, i64)], values: &HashMap<String, i64>) -> String {
+    let path = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("src/registry.in");
+
+    println!("cargo:rerun-if-changed={}", path.display());
+
+    let typed = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("Could not read {}: {}", path.display(), e));
+
+    let mut names = HashSet::new();
+    let mut addresses = HashSet::new();
+
+    // Entries are `Name: SMP_DEFINE, ...;`
+    for line in typed.lines().map(str::trim) {
+        if line.starts_with("//") || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((name, rest)) = line.split_once(": ") else {
+            continue;
+        };
+
+        let define = rest.split(',').next().unwrap_or_default().trim();
+
+        let address = values.get(define).unwrap_or_else(|| {
+            panic!(
+                "{} in src/registry.in is not defined in simplemotion_defs.h",
+                define
+            )
+        });
+
+        names.insert(name.to_string());
+        addresses.insert(*address);
+    }
+
+    let max_address = values["SMP_ADDRESS_BITS_MASK"];
+
+    let mut out = format!(
+        "parameters! {{\n{}\n// Generated from simplemotion_defs.h\n",
+        typed
+    );
+
+    for (define, address) in defines {
+        let Some(rest) = define.strip_prefix("SMP_") else {
+            continue;
+        };
+
+        // Masks, command status values, system control commands and control bits share the
+        // prefix but aren't parameters. Values of other parameters usually collide with a
+        // parameter address, so duplicate addresses are skipped too.
+        if define.contains("_MASK")
+            || [
+                "SMP_CMD_STATUS_",
+                "SMP_SYSTEM_CONTROL_",
+                "SMP_CB1_",
+                "SMP_CB2_",
+            ]
+            .iter()
+            .any(|prefix| define.starts_with(prefix))
+            || !(1..=max_address).contains(address)
+            || !addresses.insert(*address)
+        {
+            continue;
+        }
+
+        let name = rest
+            .split('_')
+            .map(|word| {
+                let word = word.to_ascii_lowercase();
+                let mut chars = word.chars();
+
+                chars
+                    .next()
+                    .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                    .unwrap_or_default()
+            })
+            .collect::<String>();
+
+        if !names.insert(name.clone()) {
+            panic!(
+                "Generated parameter name {} for {} is already used. Add {} to src/registry.in",
+                name, define, define
+            );
+        }
+
+        out.push_str(&format!(
+            "/// `{}`. Type, unit and access aren't known, see the drive documentation.\n{}: {}, i32, Unknown, rw;\n",
+            define, name, define
+        ));
+    }
+
+    out.push_str("}\n");
+
+    out
+}
+
+/// Remove `//` and `/* */` comments.
+fn strip_comments(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('/', Some('/')) => {
+                while chars.peek().is_some_and(|c| *c != '\n') {
+                    chars.next();
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+
+                let mut prev = ' ';
+
+                for c in chars.by_ref() {
+                    // Keep line structure so `#define`s after a block comment stay on their own line
+                    if c == '\n' {
+                        out.push('\n');
+                    }
+
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+
+                    prev = c;
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Tiny C integer expression evaluator supporting literals, previously defined names, `BV()`,
+/// parentheses, unary `-`/`~` and the usual binary operators.
+struct Expr<'a> {
+    tokens: Vec<String>,
+    pos: usize,
+    values: &'a HashMap<String, i64>,
+}
+
+impl<'a> Expr<'a> {
+    fn new(expr: &str, values: &'a HashMap<String, i64>) -> Self {
+        let mut tokens = Vec::new();
+        let mut chars = expr.chars().peekable();
+
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+            } else if c.is_ascii_alphanumeric() || c == '_' {
+                let mut token = String::new();
+
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' {
+                        token.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+
+                tokens.push(token);
+            } else if c == '<' || c == '>' {
+                chars.next();
+
+                if chars.peek() == Some(&c) {
+                    chars.next();
+                }
+
+                tokens.push(format!("{}{}", c, c));
+            } else {
+                tokens.push(c.to_string());
+                chars.next();
+            }
+        }
+
+        Self {
+            tokens,
+            pos: 0,
+            values,
+        }
+    }
+
+    fn parse(mut self) -> Option<i64> {
+        let value = self.binary(0)?;
+
+        // Trailing garbage means this isn't a plain integer constant
+        if self.pos == self.tokens.len() {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn precedence(op: &str) -> Option<u8> {
+        match op {
+            "|" => Some(1),
+            "^" => Some(2),
+            "&" => Some(3),
+            "<<" | ">>" => Some(4),
+            "+" | "-" => Some(5),
+            "*" | "/" => Some(6),
+            _ => None,
+        }
+    }
+
+    fn binary(&mut self, min_precedence: u8) -> Option<i64> {
+        let mut lhs = self.unary()?;
+
+        while let Some(precedence) = self.peek().and_then(Self::precedence) {
+            if precedence < min_precedence {
+                break;
+            }
+
+            let op = self.next()?;
+            let rhs = self.binary(precedence + 1)?;
+
+            lhs = match op.as_str() {
+                "|" => lhs | rhs,
+                "^" => lhs ^ rhs,
+                "&" => lhs & rhs,
+                "<<" => lhs.checked_shl(u32::try_from(rhs).ok()?)?,
+                ">>" => lhs.checked_shr(u32::try_from(rhs).ok()?)?,
+                "+" => lhs.checked_add(rhs)?,
+                "-" => lhs.checked_sub(rhs)?,
+                "*" => lhs.checked_mul(rhs)?,
+                "/" => lhs.checked_div(rhs)?,
+                _ => unreachable!(),
+            };
+        }
+
+        Some(lhs)
+    }
+
+    fn unary(&mut self) -> Option<i64> {
+        match self.next()?.as_str() {
+            "-" => Some(-self.unary()?),
+            "~" => Some(!self.unary()?),
+            "(" => {
+                let value = self.binary(0)?;
+
+                (self.next()? == ")").then_some(value)
+            }
+            "BV" => {
+                let bit = self.unary()?;
+
+                1i64.checked_shl(u32::try_from(bit).ok()?)
+            }
+            token if token.starts_with(|c: char| c.is_ascii_digit()) => {
+                let token = token.trim_end_matches(['u', 'U', 'l', 'L']);
+
+                if let Some(hex) = token
+                    .strip_prefix("0x")
+                    .or_else(|| token.strip_prefix("0X"))
+                {
+                    i64::from_str_radix(hex, 16).ok()
+                } else {
+                    token.parse().ok()
+                }
+            }
+            token => self.values.get(token).copied(),
+        }
+    }
+}
@@ -5,7 +5,7 @@ use linuxcnc_hal::{
     error::PinRegisterError, hal_pin::InputPin, hal_pin::OutputPin, prelude::*, HalComponent,
     RegisterResources, Resources,
 };
-use simplemotion::{Argon, ControlMode};
+use simplemotion::{Argon, ControlMode, ShutdownPolicy};
 use smol::{LocalExecutor, Timer};
 use std::{error::Error, time::Duration};
 
@@ -98,6 +98,8 @@ fn inner() -> Result<(), Box<dyn Error>> {
 
     let mut argon = Argon::connect(&device, address)?;
 
+    argon.set_shutdown_policy(ShutdownPolicy::ZeroSetpoint);
+
     argon.clear_faults()?;
 
     let comp: HalComponent<Comp> = HalComponent::new("argon")?;
@@ -137,9 +139,9 @@ fn inner() -> Result<(), Box<dyn Error>> {
     //     Ok::<(), Box<dyn Error>>(())
     // }))?;
 
-    // Bare minimum safe state on shutdown.
-    // FIXME: Check if I need to set anything else.
-    argon.set_velocity_rps(0.0)?;
+    // Stop the spindle on shutdown. This also happens if the driver panics or loses
+    // communication with the drive.
+    argon.shutdown()?;
 
     Ok(())
 }
@@ -193,6 +195,10 @@ fn loop_tick(argon: &mut Argon, pins: &Comp, mut state: State) -> Result<State,
             if argon.faults()?.any() {
                 log::error!("Could not clear faults");
 
+                for diagnosis in argon.diagnose_faults()? {
+                    log::error!("{}", diagnosis);
+                }
+
                 state = State::Idle;
             } else {
                 state = State::Spindle;
@@ -234,7 +240,7 @@ fn loop_tick(argon: &mut Argon, pins: &Comp, mut state: State) -> Result<State,
             log::trace!("Orienting...");
 
             // Set status and change mode when orient completes
-            if !argon.status()?.homing {
+            if !argon.status()?.homing() {
                 log::debug!("Oriented");
 
                 // Reset homing flag so we can orient multiple times in a row
@@ -0,0 +1,328 @@
+//! Async wrapper around [`Argon`].
+
+use crate::{Argon, Error, Faults, Status, POLL_INTERVAL};
+use futures_lite::future;
+use std::{
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    task::{Poll, Waker},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// An [`Argon`] that can be used from async code with any executor.
+///
+/// Bus calls block for up to the 100ms bus timeout, so the drive is moved to a dedicated I/O
+/// thread. Each method sends a request to the thread and returns a future that completes when the
+/// drive has answered. The thread stops when the `AsyncArgon` is dropped.
+///
+/// ```rust
+/// use futures_lite::future;
+/// use simplemotion::{
+///     sim::{Sim, SimDrive},
+///     Argon, AsyncArgon,
+/// };
+///
+/// let sim = Sim::new();
+/// sim.add_drive(1, SimDrive::new());
+///
+/// let argon = AsyncArgon::new(Argon::with_sim(&sim, 1)?);
+///
+/// future::block_on(async {
+///     argon.set_velocity_rps(5.0).await?;
+///
+///     assert!(!argon.faults().await?.any());
+///
+///     Ok::<(), simplemotion::Error>(())
+/// })?;
+/// # Ok::<(), simplemotion::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct AsyncArgon {
+    sender: Option<mpsc::Sender<Request>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+type Job = Box<dyn FnOnce(&mut Argon) + Send>;
+
+enum Request {
+    Run(Job),
+    WaitFor(Waiter),
+}
+
+/// A pending [`AsyncArgon::wait_for`].
+struct Waiter {
+    condition: Box<dyn Fn(&Status) -> bool + Send>,
+    deadline: Instant,
+    next_poll: Instant,
+    reply: Reply<Result<Status, Error>>,
+}
+
+impl AsyncArgon {
+    /// Move a connected drive to a new I/O thread.
+    pub fn new(argon: Argon) -> Self {
+        let (sender, receiver) = mpsc::channel();
+
+        let thread = thread::Builder::new()
+            .name(format!("simplemotion-{}", argon.address()))
+            .spawn(move || io_thread(argon, receiver))
+            .expect("Failed to spawn I/O thread");
+
+        Self {
+            sender: Some(sender),
+            thread: Some(thread),
+        }
+    }
+
+    fn send(&self, request: Request) -> Result<(), Error> {
+        self.sender
+            .as_ref()
+            .and_then(|sender| sender.send(request).ok())
+            .ok_or(Error::Disconnected)
+    }
+
+    /// Run a closure with the drive on the I/O thread.
+    ///
+    /// Use this for any [`Argon`] method without an async equivalent here.
+    pub async fn run<F, R>(&self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut Argon) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (reply, receiver) = reply();
+
+        self.send(Request::Run(Box::new(move |argon| reply.send(f(argon)))))?;
+
+        receiver.recv().await
+    }
+
+    /// Get drive status.
+    pub async fn status(&self) -> Result<Status, Error> {
+        self.run(|argon| argon.status()).await?
+    }
+
+    /// Get drive faults.
+    pub async fn faults(&self) -> Result<Faults, Error> {
+        self.run(|argon| argon.faults()).await?
+    }
+
+    /// Set the velocity by RPS value.
+    pub async fn set_velocity_rps(&self, rps: f64) -> Result<(), Error> {
+        self.run(move |argon| argon.set_velocity_rps(rps)).await?
+    }
+
+    /// Put the drive into position mode and search for the home (index) pulse.
+    ///
+    /// See [`Argon::home`].
+    pub async fn home(&self, offset: f64) -> Result<(), Error> {
+        self.run(move |argon| argon.home(offset)).await?
+    }
+
+    /// Wait until the drive status matches a condition, returning the matching status.
+    ///
+    /// Other requests are still handled while waiting.
+    ///
+    /// ```rust
+    /// # use futures_lite::future;
+    /// # use simplemotion::{sim::{Sim, SimDrive}, Argon, AsyncArgon};
+    /// # use std::time::Duration;
+    /// # let sim = Sim::new();
+    /// # sim.add_drive(1, SimDrive::new());
+    /// # let argon = AsyncArgon::new(Argon::with_sim(&sim, 1)?);
+    /// future::block_on(async {
+    ///     argon.home(0.0).await?;
+    ///
+    ///     argon
+    ///         .wait_for(|status| !status.homing(), Duration::from_secs(10))
+    ///         .await
+    /// })?;
+    /// # Ok::<(), simplemotion::Error>(())
+    /// ```
+    pub async fn wait_for<F>(&self, condition: F, timeout: Duration) -> Result<Status, Error>
+    where
+        F: Fn(&Status) -> bool + Send + 'static,
+    {
+        let (reply, receiver) = reply();
+        let now = Instant::now();
+
+        self.send(Request::WaitFor(Waiter {
+            condition: Box::new(condition),
+            deadline: now + timeout,
+            next_poll: now,
+            reply,
+        }))?;
+
+        receiver.recv().await?
+    }
+}
+
+impl Drop for AsyncArgon {
+    fn drop(&mut self) {
+        // Disconnecting the channel stops the thread
+        self.sender.take();
+
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("I/O thread panicked");
+            }
+        }
+    }
+}
+
+fn io_thread(mut argon: Argon, receiver: mpsc::Receiver<Request>) {
+    let mut waiters: Vec<Waiter> = Vec::new();
+
+    loop {
+        let request = match waiters.iter().map(|waiter| waiter.next_poll).min() {
+            Some(next_poll) => {
+                receiver.recv_timeout(next_poll.saturating_duration_since(Instant::now()))
+            }
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match request {
+            Ok(Request::Run(job)) => job(&mut argon),
+            Ok(Request::WaitFor(waiter)) => waiters.push(waiter),
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        let now = Instant::now();
+
+        for mut waiter in std::mem::take(&mut waiters) {
+            if waiter.next_poll > now {
+                waiters.push(waiter);
+
+                continue;
+            }
+
+            match argon.status() {
+                Ok(status) if (waiter.condition)(&status) => waiter.reply.send(Ok(status)),
+                Ok(_) if now >= waiter.deadline => waiter.reply.send(Err(Error::Timeout)),
+                Ok(_) => {
+                    waiter.next_poll = now + POLL_INTERVAL;
+
+                    waiters.push(waiter);
+                }
+                Err(e) => waiter.reply.send(Err(e)),
+            }
+        }
+    }
+
+    log::debug!("I/O thread for drive {} stopped", argon.address());
+}
+
+/// Shared state of a single value sent from the I/O thread to a future.
+#[derive(Debug)]
+struct Slot<T> {
+    value: Option<T>,
+    waker: Option<Waker>,
+
+    /// Sender was dropped without sending, e.g. because the I/O thread stopped.
+    closed: bool,
+}
+
+/// Sending half of a reply, used on the I/O thread.
+struct Reply<T>(Arc<Mutex<Slot<T>>>);
+
+/// Receiving half of a reply.
+struct ReplyReceiver<T>(Arc<Mutex<Slot<T>>>);
+
+fn reply<T>() -> (Reply<T>, ReplyReceiver<T>) {
+    let slot = Arc::new(Mutex::new(Slot {
+        value: None,
+        waker: None,
+        closed: false,
+    }));
+
+    (Reply(slot.clone()), ReplyReceiver(slot))
+}
+
+impl<T> Reply<T> {
+    fn send(self, value: T) {
+        self.0.lock().unwrap().value = Some(value);
+
+        // Drop wakes the receiver
+    }
+}
+
+impl<T> Drop for Reply<T> {
+    fn drop(&mut self) {
+        let mut slot = self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        slot.closed = true;
+
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> ReplyReceiver<T> {
+    async fn recv(self) -> Result<T, Error> {
+        future::poll_fn(|cx| {
+            let mut slot = self
+                .0
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+            if let Some(value) = slot.value.take() {
+                Poll::Ready(Ok(value))
+            } else if slot.closed {
+                Poll::Ready(Err(Error::Disconnected))
+            } else {
+                slot.waker = Some(cx.waker().clone());
+
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        sim::{Sim, SimDrive},
+        ControlMode,
+    };
+
+    #[test]
+    fn wait_for_velocity() {
+        let sim = Sim::new();
+        sim.add_drive(1, SimDrive::new());
+        sim.set_transaction_time(Duration::from_millis(10));
+
+        let argon = AsyncArgon::new(Argon::with_sim(&sim, 1).unwrap());
+
+        future::block_on(async {
+            argon
+                .run(|argon| argon.set_control_mode(ControlMode::Velocity))
+                .await
+                .unwrap()
+                .unwrap();
+            argon.set_velocity_rps(10.0).await.unwrap();
+
+            let status = argon
+                .wait_for(|status| status.target_reached(), Duration::from_secs(5))
+                .await
+                .unwrap();
+
+            assert!(status.target_reached());
+            assert!(!status.standing_still());
+
+            assert!(matches!(
+                argon
+                    .wait_for(|status| status.homing(), Duration::from_millis(50))
+                    .await,
+                Err(Error::Timeout)
+            ));
+        });
+    }
+}
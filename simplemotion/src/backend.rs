@@ -0,0 +1,156 @@
+//! Bus access, either through the SimpleMotionV2 C library or the native Rust implementation.
+//!
+//! Both backends expose the same `Connection` interface. With the `sim` feature, [`Handle`]
+//! additionally allows a `Sim` to stand in for a real bus.
+
+#[cfg(not(any(feature = "sys", feature = "pure-rust")))]
+compile_error!("One of the `sys` or `pure-rust` features must be enabled");
+
+#[cfg(feature = "pure-rust")]
+mod native;
+#[cfg(not(feature = "pure-rust"))]
+mod sys;
+
+#[cfg(feature = "sim")]
+use crate::sim::Sim;
+use crate::{
+    batch::{Op, MAX_OPS_PER_TRANSACTION},
+    transport::SharedTransport,
+    StatusCode,
+};
+#[cfg(feature = "pure-rust")]
+use native::Connection;
+#[cfg(not(feature = "pure-rust"))]
+pub(crate) use sys::Connection;
+
+/// A real or simulated bus.
+#[derive(Debug)]
+pub(crate) enum Handle {
+    Connection(Connection),
+    #[cfg(feature = "sim")]
+    Sim(Sim),
+}
+
+impl Handle {
+    /// Open the bus on the given device, e.g. `/dev/ttyUSB0`.
+    pub fn open(device: &str) -> Result<Self, StatusCode> {
+        Connection::open(device).map(Self::Connection)
+    }
+
+    /// Open the bus over a user provided transport.
+    pub fn with_transport(transport: SharedTransport) -> Result<Self, StatusCode> {
+        Connection::with_transport(transport).map(Self::Connection)
+    }
+
+    /// Get the C library connection, if this isn't a simulated bus.
+    #[cfg(not(feature = "pure-rust"))]
+    pub fn connection(&self) -> Option<&Connection> {
+        match self {
+            Self::Connection(connection) => Some(connection),
+            #[cfg(feature = "sim")]
+            Self::Sim(_) => None,
+        }
+    }
+
+    /// Close the bus and open it again.
+    pub fn reopen(&self) -> Result<(), StatusCode> {
+        match self {
+            Self::Connection(connection) => connection.reopen(),
+            #[cfg(feature = "sim")]
+            Self::Sim(_) => Ok(()),
+        }
+    }
+
+    /// Close the bus.
+    pub fn close(&self) -> StatusCode {
+        match self {
+            Self::Connection(connection) => connection.close(),
+            #[cfg(feature = "sim")]
+            Self::Sim(_) => StatusCode::Ok,
+        }
+    }
+
+    /// Set a parameter on the device at the given address.
+    pub fn set_parameter(&self, address: u8, parameter: u16, value: i32) -> StatusCode {
+        match self {
+            Self::Connection(connection) => connection.set_parameter(address, parameter, value),
+            #[cfg(feature = "sim")]
+            Self::Sim(sim) => sim.set_parameter(address, parameter, value),
+        }
+    }
+
+    /// Read a parameter from the device at the given address.
+    pub fn read_parameter(&self, address: u8, parameter: u16) -> Result<i32, StatusCode> {
+        match self {
+            Self::Connection(connection) => connection.read_parameter(address, parameter),
+            #[cfg(feature = "sim")]
+            Self::Sim(sim) => sim.read_parameter(address, parameter),
+        }
+    }
+
+    /// Run a list of operations on the device at the given address, returning one result per
+    /// operation.
+    ///
+    /// Operations are sent in as few transactions as possible.
+    pub fn execute(
+        &self,
+        address: u8,
+        ops: &[Op],
+    ) -> Result<Vec<Result<i32, StatusCode>>, StatusCode> {
+        let mut results = Vec::with_capacity(ops.len());
+
+        for chunk in ops.chunks(MAX_OPS_PER_TRANSACTION) {
+            results.extend(match self {
+                Self::Connection(connection) => connection.execute(address, chunk)?,
+                #[cfg(feature = "sim")]
+                Self::Sim(sim) => sim.execute(address, chunk)?,
+            });
+        }
+
+        Ok(results)
+    }
+
+    /// Send two setpoint words to the device at the given address and receive two feedback words.
+    pub fn fast_update_cycle(
+        &self,
+        address: u8,
+        out1: u16,
+        out2: u16,
+    ) -> Result<(u16, u16), StatusCode> {
+        match self {
+            Self::Connection(connection) => connection.fast_update_cycle(address, out1, out2),
+            #[cfg(feature = "sim")]
+            Self::Sim(sim) => sim.fast_update_cycle(address, out1, out2),
+        }
+    }
+
+    /// Get the status of all bus operations since the last reset.
+    pub fn cumulative_status(&self) -> i32 {
+        match self {
+            Self::Connection(connection) => connection.cumulative_status(),
+            #[cfg(feature = "sim")]
+            Self::Sim(sim) => sim.cumulative_status(),
+        }
+    }
+
+    /// Reset cumulative status.
+    pub fn reset_cumulative_status(&self) -> StatusCode {
+        match self {
+            Self::Connection(connection) => connection.reset_cumulative_status(),
+            #[cfg(feature = "sim")]
+            Self::Sim(sim) => sim.reset_cumulative_status(),
+        }
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        log::debug!("Close bus");
+
+        let result = self.close();
+
+        if result.is_err() {
+            log::error!("Failed to close bus handle: {:?}", result);
+        }
+    }
+}
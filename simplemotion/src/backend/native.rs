@@ -0,0 +1,494 @@
+use crate::{
+    batch::Op,
+    defs::{SMP_CMD_STATUS_ACK, SMP_RETURN_PARAM_ADDR, SMP_RETURN_PARAM_LEN},
+    protocol::{
+        self, Command, Return, SMCMD_INSTANT_CMD, SMCMD_INSTANT_CMD_RET, SMPRET_32B,
+        SMPRET_CMD_STATUS,
+    },
+    transport::{Io, SharedTransport, Source, DEFAULT_BAUDRATE, DEFAULT_TIMEOUT},
+    StatusCode,
+};
+use std::{
+    fmt,
+    io::Write,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+/// Bus connection backed by the native Rust protocol implementation.
+pub(crate) struct Connection {
+    source: Source,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    /// Open transport. `None` once the bus is closed.
+    transport: Option<SharedTransport>,
+
+    /// Every status returned since the last reset, OR'd together.
+    cumulative_status: u32,
+}
+
+impl fmt::Debug for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Connection")
+            .field("source", &self.source)
+            .finish()
+    }
+}
+
+impl Connection {
+    /// Open the bus on the given device, e.g. `/dev/ttyUSB0`.
+    pub fn open(device: &str) -> Result<Self, StatusCode> {
+        Self::from_source(Source::Device(device.to_string()))
+    }
+
+    /// Open the bus over a user provided transport.
+    pub fn with_transport(transport: SharedTransport) -> Result<Self, StatusCode> {
+        Self::from_source(Source::Transport(transport))
+    }
+
+    fn from_source(source: Source) -> Result<Self, StatusCode> {
+        let transport = Self::open_source(&source)?;
+
+        Ok(Self {
+            source,
+            inner: Mutex::new(Inner {
+                transport: Some(transport),
+                cumulative_status: 0,
+            }),
+        })
+    }
+
+    fn open_source(source: &Source) -> Result<SharedTransport, StatusCode> {
+        let transport: SharedTransport = match source {
+            Source::Device(device) => {
+                let port = serialport::new(device, DEFAULT_BAUDRATE)
+                    .open()
+                    .map_err(|e| {
+                        log::debug!("Failed to open serial port {}: {}", device, e);
+
+                        StatusCode::ErrBus
+                    })?;
+
+                Arc::new(Mutex::new(port))
+            }
+            Source::Transport(transport) => transport.clone(),
+        };
+
+        {
+            let mut t = transport.lock().map_err(|_| StatusCode::ErrBus)?;
+
+            t.set_baudrate(DEFAULT_BAUDRATE)
+                .and_then(|_| t.set_timeout(DEFAULT_TIMEOUT))
+                .map_err(protocol::io_status)?;
+        }
+
+        Ok(transport)
+    }
+
+    /// Close the bus and open it again from the same device or transport.
+    pub fn reopen(&self) -> Result<(), StatusCode> {
+        let result = self.close();
+
+        log::debug!("Closing bus, status: {}", result);
+
+        let transport = Self::open_source(&self.source)?;
+
+        self.lock().transport = Some(transport);
+
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Close the bus.
+    pub fn close(&self) -> StatusCode {
+        match self.lock().transport.take() {
+            Some(_) => StatusCode::Ok,
+            None => StatusCode::ErrBus,
+        }
+    }
+
+    /// Set a parameter on the device at the given address.
+    pub fn set_parameter(&self, address: u8, parameter: u16, value: i32) -> StatusCode {
+        let result = self.lock().execute(
+            address,
+            &[
+                Command::SetParamAddr(SMP_RETURN_PARAM_LEN as u16),
+                Command::Value24(SMPRET_CMD_STATUS),
+                Command::SetParamAddr(parameter),
+                Command::Value32(value),
+            ],
+        );
+
+        match result.as_deref() {
+            Ok([.., Return::CommandStatus(status)]) if u32::from(*status) == SMP_CMD_STATUS_ACK => {
+                StatusCode::Ok
+            }
+            Ok(_) => StatusCode::ErrParameter,
+            Err(e) => *e,
+        }
+    }
+
+    /// Read a parameter from the device at the given address.
+    pub fn read_parameter(&self, address: u8, parameter: u16) -> Result<i32, StatusCode> {
+        let returns = self.lock().execute(
+            address,
+            &[
+                Command::SetParamAddr(SMP_RETURN_PARAM_LEN as u16),
+                Command::Value24(SMPRET_32B),
+                Command::SetParamAddr(SMP_RETURN_PARAM_ADDR as u16),
+                Command::Value24(parameter.into()),
+            ],
+        )?;
+
+        match returns.as_slice() {
+            [.., Return::Value(value)] => Ok(*value),
+            _ => Err(StatusCode::ErrParameter),
+        }
+    }
+
+    /// Run a list of operations on the device at the given address in a single transaction.
+    pub fn execute(
+        &self,
+        address: u8,
+        ops: &[Op],
+    ) -> Result<Vec<Result<i32, StatusCode>>, StatusCode> {
+        // Each operation sets its own return length so it gets its own return value
+        let commands = ops
+            .iter()
+            .flat_map(|op| match *op {
+                Op::Read(parameter) => [
+                    Command::SetParamAddr(SMP_RETURN_PARAM_LEN as u16),
+                    Command::Value24(SMPRET_32B),
+                    Command::SetParamAddr(SMP_RETURN_PARAM_ADDR as u16),
+                    Command::Value24(parameter.into()),
+                ],
+                Op::Write(parameter, value) => [
+                    Command::SetParamAddr(SMP_RETURN_PARAM_LEN as u16),
+                    Command::Value24(SMPRET_CMD_STATUS),
+                    Command::SetParamAddr(parameter),
+                    Command::Value32(value),
+                ],
+            })
+            .collect::<Vec<_>>();
+
+        let returns = self.lock().execute(address, &commands)?;
+
+        let results = ops
+            .iter()
+            .zip(returns.chunks(4))
+            .map(|(op, returns)| match (op, returns) {
+                (Op::Read(_), [.., Return::Value(value)]) => Ok(*value),
+                (Op::Write(..), [.., Return::CommandStatus(status)])
+                    if u32::from(*status) == SMP_CMD_STATUS_ACK =>
+                {
+                    Ok(0)
+                }
+                _ => Err(StatusCode::ErrParameter),
+            })
+            .collect();
+
+        Ok(results)
+    }
+
+    /// Send two setpoint words to the device and receive two feedback words.
+    pub fn fast_update_cycle(
+        &self,
+        address: u8,
+        out1: u16,
+        out2: u16,
+    ) -> Result<(u16, u16), StatusCode> {
+        let mut inner = self.lock();
+
+        let result = inner.fast_update_cycle(address, out1, out2);
+
+        inner.cumulative_status |= match &result {
+            Ok(_) => StatusCode::Ok,
+            Err(e) => *e,
+        }
+        .bits() as u32;
+
+        result
+    }
+
+    /// Get the status of all bus operations since the last reset.
+    pub fn cumulative_status(&self) -> i32 {
+        self.lock().cumulative_status as i32
+    }
+
+    /// Reset cumulative status.
+    pub fn reset_cumulative_status(&self) -> StatusCode {
+        self.lock().cumulative_status = 0;
+
+        StatusCode::Ok
+    }
+}
+
+impl Inner {
+    /// Send a list of commands to a device and collect one return value per command.
+    fn execute(&mut self, address: u8, commands: &[Command]) -> Result<Vec<Return>, StatusCode> {
+        let result = self.transaction(address, commands);
+
+        self.cumulative_status |= match &result {
+            Ok(_) => StatusCode::Ok,
+            Err(e) => *e,
+        }
+        .bits() as u32;
+
+        result
+    }
+
+    fn fast_update_cycle(
+        &mut self,
+        address: u8,
+        out1: u16,
+        out2: u16,
+    ) -> Result<(u16, u16), StatusCode> {
+        let transport = self.transport.as_ref().ok_or(StatusCode::ErrBus)?;
+        let mut transport = transport.lock().map_err(|_| StatusCode::ErrBus)?;
+
+        transport.purge().map_err(protocol::io_status)?;
+
+        let mut io = Io(&mut *transport);
+
+        io.write_all(&protocol::encode_fast_update(address, out1, out2))
+            .map_err(protocol::io_status)?;
+        io.flush().map_err(protocol::io_status)?;
+
+        protocol::read_fast_update(&mut io)
+    }
+
+    fn transaction(
+        &mut self,
+        address: u8,
+        commands: &[Command],
+    ) -> Result<Vec<Return>, StatusCode> {
+        let transport = self.transport.as_ref().ok_or(StatusCode::ErrBus)?;
+        let mut transport = transport.lock().map_err(|_| StatusCode::ErrBus)?;
+
+        let payload = protocol::encode_commands(commands)?;
+        let packet = protocol::encode_packet(SMCMD_INSTANT_CMD, address, &payload);
+
+        // Discard anything left over from a previous, timed out transaction
+        transport.purge().map_err(protocol::io_status)?;
+
+        let mut io = Io(&mut *transport);
+
+        io.write_all(&packet).map_err(protocol::io_status)?;
+        io.flush().map_err(protocol::io_status)?;
+
+        let reply = protocol::read_packet(&mut io)?;
+
+        if reply.command != SMCMD_INSTANT_CMD_RET {
+            log::debug!(
+                "Unexpected reply command {} from address {}",
+                reply.command,
+                reply.address
+            );
+
+            return Err(StatusCode::ErrCommunication);
+        }
+
+        let returns = protocol::decode_returns(&reply.payload)?;
+
+        if returns.len() != commands.len() {
+            return Err(StatusCode::ErrLength);
+        }
+
+        Ok(returns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{batch::Op, transport::Transport, Bus, StatusCode};
+    use std::{collections::VecDeque, io, time::Duration};
+
+    /// In-memory bus that checks each request against an expected frame and answers with a canned
+    /// reply. Reads time out once the reply is used up.
+    struct Script {
+        exchanges: VecDeque<(&'static [u8], &'static [u8])>,
+        written: Vec<u8>,
+        reply: VecDeque<u8>,
+    }
+
+    impl Script {
+        fn bus(exchanges: &[(&'static [u8], &'static [u8])]) -> Bus {
+            Bus::with_transport(Self {
+                exchanges: exchanges.iter().copied().collect(),
+                written: Vec::new(),
+                reply: VecDeque::new(),
+            })
+            .unwrap()
+        }
+    }
+
+    impl Transport for Script {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.reply.is_empty() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+
+            let len = buf.len().min(self.reply.len());
+
+            for (byte, reply) in buf.iter_mut().zip(self.reply.drain(..len)) {
+                *byte = reply;
+            }
+
+            Ok(len)
+        }
+
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.extend_from_slice(buf);
+
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            let request = std::mem::take(&mut self.written);
+
+            if let Some((expected, reply)) = self.exchanges.pop_front() {
+                assert_eq!(request, expected);
+
+                self.reply.extend(reply);
+            }
+
+            Ok(())
+        }
+
+        fn set_timeout(&mut self, _timeout: Duration) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn set_baudrate(&mut self, _baudrate: u32) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn purge(&mut self) -> io::Result<()> {
+            self.reply.clear();
+
+            Ok(())
+        }
+    }
+
+    // Frames are written out in full, with CRCs worked out independently of `protocol::crc16`, so
+    // these tests pin the wire format as well as the framing code.
+
+    /// Read `SMP_STATUS` (553) from address 1 with a 32 bit return.
+    const READ_STATUS: &[u8] = &[
+        0x24, 0x0a, 0x01, 0x80, 0x09, 0x40, 0x00, 0x00, 0x80, 0x0a, 0x40, 0x02, 0x29, 0x0f, 0xc4,
+    ];
+
+    #[test]
+    fn read_parameter() {
+        let bus = Script::bus(&[
+            // 30 bit return
+            (
+                READ_STATUS,
+                &[
+                    0x25, 0x0c, 0x01, 0xc0, 0x00, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3f, 0xff,
+                    0xff, 0xfe, 0x00, 0x51,
+                ],
+            ),
+            // 22 bit return
+            (
+                READ_STATUS,
+                &[
+                    0x25, 0x0b, 0x01, 0xc0, 0x00, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7f, 0xff,
+                    0x9c, 0xeb, 0xb4,
+                ],
+            ),
+            // 14 bit return
+            (
+                READ_STATUS,
+                &[
+                    0x25, 0x0a, 0x01, 0xc0, 0x00, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0xbf, 0xfd,
+                    0xb7, 0xd4,
+                ],
+            ),
+            // Bad CRC
+            (
+                READ_STATUS,
+                &[
+                    0x25, 0x0a, 0x01, 0xc0, 0x00, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0xbf, 0xfd,
+                    0xb7, 0xd5,
+                ],
+            ),
+        ]);
+
+        let handle = bus.handle();
+
+        assert_eq!(handle.read_parameter(1, 553), Ok(-2));
+        assert_eq!(handle.read_parameter(1, 553), Ok(-100));
+        assert_eq!(handle.read_parameter(1, 553), Ok(-3));
+        assert_eq!(
+            handle.read_parameter(1, 553),
+            Err(StatusCode::ErrCommunication)
+        );
+    }
+
+    #[test]
+    fn set_parameter() {
+        /// Write -1000 to `SMP_ABSOLUTE_SETPOINT` (551) on address 1.
+        const WRITE_SETPOINT: &[u8] = &[
+            0x24, 0x0b, 0x01, 0x80, 0x09, 0x40, 0x00, 0x03, 0x82, 0x27, 0x3f, 0xff, 0xfc, 0x18,
+            0x48, 0x44,
+        ];
+
+        let bus = Script::bus(&[
+            (
+                WRITE_SETPOINT,
+                &[
+                    0x25, 0x08, 0x01, 0xc0, 0x00, 0xc0, 0x00, 0xc0, 0x00, 0xc0, 0x00, 0xc2, 0x41,
+                ],
+            ),
+            (
+                WRITE_SETPOINT,
+                &[
+                    0x25, 0x08, 0x01, 0xc0, 0x00, 0xc0, 0x00, 0xc0, 0x00, 0xc0, 0x01, 0x02, 0x80,
+                ],
+            ),
+        ]);
+
+        let handle = bus.handle();
+
+        assert_eq!(handle.set_parameter(1, 551, -1000), StatusCode::Ok);
+        assert_eq!(
+            handle.set_parameter(1, 551, -1000),
+            StatusCode::ErrParameter
+        );
+    }
+
+    #[test]
+    fn batch() {
+        let bus = Script::bus(&[(
+            &[
+                0x24, 0x15, 0x02, 0x80, 0x09, 0x40, 0x00, 0x00, 0x80, 0x0a, 0x40, 0x02, 0x29, 0x80,
+                0x09, 0x40, 0x00, 0x03, 0x82, 0x27, 0x00, 0x00, 0x00, 0x05, 0xa2, 0xf7,
+            ],
+            &[
+                0x25, 0x16, 0x02, 0xc0, 0x00, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+                0x18, 0x00, 0x00, 0x00, 0x00, 0xc0, 0x00, 0xc0, 0x00, 0xc0, 0x00, 0x28, 0x60,
+            ],
+        )]);
+
+        assert_eq!(
+            bus.handle().execute(2, &[Op::Read(553), Op::Write(551, 5)]),
+            Ok(vec![Ok(280), Ok(0)])
+        );
+    }
+
+    #[test]
+    fn timeout() {
+        let bus = Script::bus(&[(READ_STATUS, &[])]);
+        let handle = bus.handle();
+
+        assert_eq!(handle.read_parameter(1, 553), Err(StatusCode::ErrNodevice));
+        assert!(StatusCode::from(handle.cumulative_status()).contains(StatusCode::ErrNodevice));
+    }
+}
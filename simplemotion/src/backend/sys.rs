@@ -0,0 +1,503 @@
+use crate::{
+    batch::Op,
+    defs::{
+        SMPCMD_24B, SMPCMD_SETPARAMADDR, SMPRET_32B, SMPRET_CMD_STATUS, SMP_CMD_STATUS_ACK,
+        SMP_RETURN_PARAM_LEN,
+    },
+    lock,
+    transport::{SharedTransport, Source, DEFAULT_TIMEOUT},
+    StatusCode,
+};
+use simplemotion_sys::{
+    getCumulativeStatus, resetCumulativeStatus, smAppendGetParamCommandToQueue,
+    smAppendSMCommandToQueue, smAppendSetParamCommandToQueue, smBusdevicePointer, smCloseBus,
+    smExecuteCommandQueue, smFastUpdateCycle, smGetQueuedGetParamReturnValue,
+    smGetQueuedSMCommandReturnValue, smGetQueuedSetParamReturnValue, smOpenBus,
+    smOpenBusWithCallbacks, smRead1Parameter, smSetParameter, smSetTimeout,
+    BusDeviceMiscOperationType, MiscOperationFlushTX, MiscOperationPurgeRX,
+};
+use std::{
+    collections::BTreeMap,
+    ffi::{CStr, CString},
+    io,
+    os::raw::c_char,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, MutexGuard,
+    },
+};
+
+/// Transports waiting to be picked up by [`transport_open`], keyed by the bus name passed to
+/// `smOpenBusWithCallbacks`.
+static PENDING_TRANSPORTS: Mutex<BTreeMap<String, SharedTransport>> = Mutex::new(BTreeMap::new());
+
+/// Used to give each transport bus a unique name.
+static TRANSPORT_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Bus connection backed by the SimpleMotionV2 C library.
+#[derive(Debug)]
+pub(crate) struct Connection {
+    handle: Mutex<i64>,
+    source: Source,
+}
+
+impl Connection {
+    /// Open the bus on the given device, e.g. `/dev/ttyUSB0`.
+    pub fn open(device: &str) -> Result<Self, StatusCode> {
+        Self::from_source(Source::Device(device.to_string()))
+    }
+
+    /// Open the bus over a user provided transport.
+    pub fn with_transport(transport: SharedTransport) -> Result<Self, StatusCode> {
+        Self::from_source(Source::Transport(transport))
+    }
+
+    fn from_source(source: Source) -> Result<Self, StatusCode> {
+        let handle = Self::open_source(&source)?;
+
+        Ok(Self {
+            handle: Mutex::new(handle),
+            source,
+        })
+    }
+
+    fn open_source(source: &Source) -> Result<i64, StatusCode> {
+        // Must be before bus open, must be between 1 and 5000ms
+        unsafe { smSetTimeout(DEFAULT_TIMEOUT.as_millis() as u16) };
+
+        let handle: i64 = match source {
+            Source::Device(device) => {
+                let device = CString::new(device.as_str())
+                    .expect("Device name could not be converted to a valid C string");
+
+                unsafe { smOpenBus(device.as_ptr()) }
+            }
+            Source::Transport(transport) => {
+                let name = format!(
+                    "transport-{}",
+                    TRANSPORT_COUNTER.fetch_add(1, Ordering::Relaxed)
+                );
+
+                lock(&PENDING_TRANSPORTS).insert(name.clone(), transport.clone());
+
+                let device = CString::new(name.as_str()).unwrap();
+
+                let handle = unsafe {
+                    smOpenBusWithCallbacks(
+                        device.as_ptr(),
+                        Some(transport_open),
+                        Some(transport_close),
+                        Some(transport_read),
+                        Some(transport_write),
+                        Some(transport_misc),
+                    )
+                };
+
+                // Make sure a failed open doesn't leave the transport behind
+                lock(&PENDING_TRANSPORTS).remove(&name);
+
+                handle
+            }
+        };
+
+        if handle >= 0 {
+            Ok(handle)
+        } else {
+            Err(handle.into())
+        }
+    }
+
+    /// Lock the bus handle.
+    ///
+    /// The C library doesn't synchronise access to a bus, so the lock is held for the duration of
+    /// every call to it.
+    pub fn handle(&self) -> MutexGuard<'_, i64> {
+        lock(&self.handle)
+    }
+
+    /// Close the bus and open it again from the same device or transport.
+    pub fn reopen(&self) -> Result<(), StatusCode> {
+        let mut handle = self.handle();
+
+        let result: StatusCode = unsafe { smCloseBus(*handle) }.into();
+
+        log::debug!("Closing bus, status: {}", result);
+
+        *handle = Self::open_source(&self.source)?;
+
+        Ok(())
+    }
+
+    /// Close the bus.
+    pub fn close(&self) -> StatusCode {
+        unsafe { smCloseBus(*self.handle()) }.into()
+    }
+
+    /// Set a parameter on the device at the given address.
+    pub fn set_parameter(&self, address: u8, parameter: u16, value: i32) -> StatusCode {
+        unsafe { smSetParameter(*self.handle(), address, parameter as i16, value) }.into()
+    }
+
+    /// Read a parameter from the device at the given address.
+    pub fn read_parameter(&self, address: u8, parameter: u16) -> Result<i32, StatusCode> {
+        let mut output = 0;
+
+        let result: StatusCode =
+            unsafe { smRead1Parameter(*self.handle(), address, parameter as i16, &mut output) }
+                .into();
+
+        if result.is_ok() {
+            Ok(output)
+        } else {
+            Err(result)
+        }
+    }
+
+    /// Run a list of operations on the device at the given address in a single transaction.
+    pub fn execute(
+        &self,
+        address: u8,
+        ops: &[Op],
+    ) -> Result<Vec<Result<i32, StatusCode>>, StatusCode> {
+        let handle = self.handle();
+
+        // Each operation sets its own return length so it gets its own return value
+        for op in ops {
+            let return_len = match op {
+                Op::Read(_) => SMPRET_32B,
+                Op::Write(..) => SMPRET_CMD_STATUS,
+            };
+
+            check(unsafe {
+                smAppendSMCommandToQueue(
+                    *handle,
+                    SMPCMD_SETPARAMADDR as i32,
+                    SMP_RETURN_PARAM_LEN as i32,
+                )
+            })?;
+            check(unsafe {
+                smAppendSMCommandToQueue(*handle, SMPCMD_24B as i32, return_len as i32)
+            })?;
+            check(match *op {
+                Op::Read(parameter) => unsafe {
+                    smAppendGetParamCommandToQueue(*handle, parameter as i16)
+                },
+                Op::Write(parameter, value) => unsafe {
+                    smAppendSetParamCommandToQueue(*handle, parameter as i16, value)
+                },
+            })?;
+        }
+
+        check(unsafe { smExecuteCommandQueue(*handle, address) })?;
+
+        let mut results = Vec::with_capacity(ops.len());
+
+        for op in ops {
+            let mut value = 0;
+
+            for _ in 0..2 {
+                check(unsafe { smGetQueuedSMCommandReturnValue(*handle, &mut value) })?;
+            }
+
+            let result = match op {
+                Op::Read(_) => {
+                    check(unsafe { smGetQueuedGetParamReturnValue(*handle, &mut value) })
+                        .map(|_| value)
+                }
+                Op::Write(..) => {
+                    match check(unsafe { smGetQueuedSetParamReturnValue(*handle, &mut value) }) {
+                        Ok(()) if value as u32 == SMP_CMD_STATUS_ACK => Ok(0),
+                        Ok(()) => Err(StatusCode::ErrParameter),
+                        Err(e) => Err(e),
+                    }
+                }
+            };
+
+            results.push(result);
+        }
+
+        Ok(results)
+    }
+
+    /// Send two setpoint words to the device and receive two feedback words.
+    pub fn fast_update_cycle(
+        &self,
+        address: u8,
+        out1: u16,
+        out2: u16,
+    ) -> Result<(u16, u16), StatusCode> {
+        let (mut in1, mut in2) = (0, 0);
+
+        check(unsafe {
+            smFastUpdateCycle(*self.handle(), address, out1, out2, &mut in1, &mut in2)
+        })?;
+
+        Ok((in1, in2))
+    }
+
+    /// Get the status of all bus operations since the last reset.
+    pub fn cumulative_status(&self) -> i32 {
+        unsafe { getCumulativeStatus(*self.handle()) }
+    }
+
+    /// Reset cumulative status.
+    pub fn reset_cumulative_status(&self) -> StatusCode {
+        unsafe { resetCumulativeStatus(*self.handle()) }.into()
+    }
+}
+
+/// Convert a C library status into a `Result`.
+fn check(status: i32) -> Result<(), StatusCode> {
+    let status = StatusCode::from(status);
+
+    if status.is_ok() {
+        Ok(())
+    } else {
+        Err(status)
+    }
+}
+
+/// Run a callback from the C library, returning `default` if it panics.
+///
+/// Unwinding into C is undefined behaviour, and a [`Transport`](crate::Transport) is user code.
+fn guard<R>(name: &str, default: R, f: impl FnOnce() -> R) -> R {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| {
+        log::error!("Transport {} panicked", name);
+
+        default
+    })
+}
+
+/// Get the transport behind a bus device pointer created by [`transport_open`].
+///
+/// # Safety
+///
+/// `device` must be a pointer returned by [`transport_open`] that hasn't been closed yet.
+unsafe fn transport<'a>(device: smBusdevicePointer) -> &'a SharedTransport {
+    &*(device as *const SharedTransport)
+}
+
+unsafe extern "C" fn transport_open(
+    name: *const c_char,
+    baudrate: i32,
+    success: *mut bool,
+) -> smBusdevicePointer {
+    let name = CStr::from_ptr(name).to_string_lossy();
+
+    let transport = guard("open", None, || {
+        let transport = lock(&PENDING_TRANSPORTS).remove(name.as_ref())?;
+
+        let result = {
+            let mut t = lock(&*transport);
+
+            t.set_baudrate(baudrate as u32)
+                .and_then(|_| t.set_timeout(DEFAULT_TIMEOUT))
+        };
+
+        match result {
+            Ok(()) => Some(transport),
+            Err(e) => {
+                log::error!("Failed to configure transport: {}", e);
+
+                None
+            }
+        }
+    });
+
+    *success = transport.is_some();
+
+    transport.map_or(std::ptr::null_mut(), |transport| {
+        Box::into_raw(Box::new(transport)) as smBusdevicePointer
+    })
+}
+
+unsafe extern "C" fn transport_close(device: smBusdevicePointer) {
+    if device.is_null() {
+        return;
+    }
+
+    let transport = Box::from_raw(device as *mut SharedTransport);
+
+    // Dropping the last reference drops the user's transport, which could panic
+    guard("close", (), move || drop(transport))
+}
+
+unsafe extern "C" fn transport_read(device: smBusdevicePointer, buf: *mut u8, size: i32) -> i32 {
+    let buf = std::slice::from_raw_parts_mut(buf, size as usize);
+    let transport = transport(device);
+
+    guard("read", -1, || match lock(&**transport).read(buf) {
+        Ok(count) => count as i32,
+        // Nothing received isn't an error, the C library handles the timeout itself
+        Err(e) if e.kind() == io::ErrorKind::TimedOut => 0,
+        Err(e) => {
+            log::debug!("Transport read failed: {}", e);
+
+            -1
+        }
+    })
+}
+
+unsafe extern "C" fn transport_write(device: smBusdevicePointer, buf: *mut u8, size: i32) -> i32 {
+    let buf = std::slice::from_raw_parts(buf, size as usize);
+    let transport = transport(device);
+
+    guard("write", -1, || match lock(&**transport).write(buf) {
+        Ok(count) => count as i32,
+        Err(e) => {
+            log::debug!("Transport write failed: {}", e);
+
+            -1
+        }
+    })
+}
+
+unsafe extern "C" fn transport_misc(
+    device: smBusdevicePointer,
+    operation: BusDeviceMiscOperationType,
+) -> bool {
+    let transport = transport(device);
+
+    guard("misc", false, || {
+        let mut transport = lock(&**transport);
+
+        let result = if operation == MiscOperationFlushTX {
+            transport.flush()
+        } else if operation == MiscOperationPurgeRX {
+            transport.purge()
+        } else {
+            return false;
+        };
+
+        result.is_ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Transport;
+    use std::{collections::VecDeque, sync::Arc, time::Duration};
+
+    /// Loopback transport: bytes written are read back.
+    #[derive(Default)]
+    struct Loopback {
+        buffer: VecDeque<u8>,
+        fail_config: bool,
+    }
+
+    impl Transport for Loopback {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if buf.len() > 64 {
+                panic!("read too large");
+            }
+
+            if self.buffer.is_empty() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+
+            let len = buf.len().min(self.buffer.len());
+
+            for (byte, value) in buf.iter_mut().zip(self.buffer.drain(..len)) {
+                *byte = value;
+            }
+
+            Ok(len)
+        }
+
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.buffer.extend(buf);
+
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn set_timeout(&mut self, _timeout: Duration) -> io::Result<()> {
+            if self.fail_config {
+                Err(io::ErrorKind::Unsupported.into())
+            } else {
+                Ok(())
+            }
+        }
+
+        fn set_baudrate(&mut self, _baudrate: u32) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn purge(&mut self) -> io::Result<()> {
+            self.buffer.clear();
+
+            Ok(())
+        }
+    }
+
+    /// Open a transport through the callbacks, as the C library would.
+    fn open(name: &str, transport: Loopback) -> (smBusdevicePointer, bool) {
+        let transport: SharedTransport = Arc::new(Mutex::new(transport));
+
+        lock(&PENDING_TRANSPORTS).insert(name.to_string(), transport);
+
+        let name = CString::new(name).unwrap();
+        let mut success = false;
+
+        let device = unsafe { transport_open(name.as_ptr(), 460_800, &mut success) };
+
+        (device, success)
+    }
+
+    #[test]
+    fn callbacks() {
+        let (device, success) = open("test-callbacks", Loopback::default());
+
+        assert!(success);
+        assert!(!device.is_null());
+
+        let mut data = [1u8, 2, 3];
+        let mut buf = [0u8; 3];
+
+        unsafe {
+            assert_eq!(transport_write(device, data.as_mut_ptr(), 3), 3);
+            assert!(transport_misc(device, MiscOperationFlushTX));
+            assert_eq!(transport_read(device, buf.as_mut_ptr(), 3), 3);
+            assert_eq!(buf, data);
+
+            // A timeout reads nothing
+            assert_eq!(transport_read(device, buf.as_mut_ptr(), 3), 0);
+
+            // A panic in the transport is an error, not an unwind into C
+            let mut large = [0u8; 65];
+            assert_eq!(transport_read(device, large.as_mut_ptr(), 65), -1);
+
+            // The transport still works after a panic poisoned its lock
+            assert_eq!(transport_write(device, data.as_mut_ptr(), 3), 3);
+            assert!(transport_misc(device, MiscOperationPurgeRX));
+            assert_eq!(transport_read(device, buf.as_mut_ptr(), 3), 0);
+
+            transport_close(device);
+        }
+    }
+
+    #[test]
+    fn open_failure() {
+        let (device, success) = open(
+            "test-open-failure",
+            Loopback {
+                fail_config: true,
+                ..Loopback::default()
+            },
+        );
+
+        assert!(!success);
+        assert!(device.is_null());
+
+        let name = CString::new("test-open-missing").unwrap();
+        let mut success = true;
+
+        assert!(unsafe { transport_open(name.as_ptr(), 460_800, &mut success) }.is_null());
+        assert!(!success);
+    }
+}
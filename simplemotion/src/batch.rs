@@ -0,0 +1,240 @@
+//! Several parameter reads and writes in one bus transaction.
+
+use crate::{
+    registry::{self, Value},
+    Argon, Error, StatusCode,
+};
+use std::marker::PhantomData;
+
+/// Most operations sent in a single transaction. Larger batches are split into several
+/// transactions to keep the command and reply packets within the SimpleMotion payload limit.
+pub(crate) const MAX_OPS_PER_TRANSACTION: usize = 6;
+
+/// A queued batch operation.
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum Op {
+    /// Read a parameter.
+    Read(u16),
+
+    /// Write a value to a parameter.
+    Write(u16, i32),
+}
+
+impl Op {
+    fn address(&self) -> u16 {
+        match *self {
+            Self::Read(address) | Self::Write(address, _) => address,
+        }
+    }
+}
+
+/// Parameter reads and writes queued up to be sent to a drive together.
+///
+/// Created by [`Argon::batch`]. Each queued operation returns an [`Entry`] which is used to get
+/// its result from the [`BatchResults`] returned by [`Batch::execute`].
+///
+/// ```rust
+/// use simplemotion::{
+///     registry::{AbsoluteSetpoint, ActualVelocity, Status},
+///     sim::{Sim, SimDrive},
+///     Argon,
+/// };
+///
+/// let sim = Sim::new();
+/// sim.add_drive(1, SimDrive::new());
+///
+/// let argon = Argon::with_sim(&sim, 1)?;
+///
+/// let mut batch = argon.batch();
+///
+/// let setpoint = batch.set::<AbsoluteSetpoint>(100)?;
+/// let status = batch.get::<Status>();
+/// let velocity = batch.get::<ActualVelocity>();
+///
+/// let results = batch.execute()?;
+///
+/// assert!(results.status(setpoint).is_ok());
+///
+/// let status: simplemotion::Status = results.get(status)?;
+/// let velocity: i16 = results.get(velocity)?;
+/// # Ok::<(), simplemotion::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Batch<'a> {
+    argon: &'a Argon,
+    ops: Vec<Op>,
+}
+
+/// Handle to the result of a queued batch operation, where `T` is the type of value read.
+///
+/// Writes produce an `Entry<()>`, which only has a [status](BatchResults::status).
+#[derive(Debug)]
+pub struct Entry<T> {
+    index: usize,
+    _value: PhantomData<fn() -> T>,
+}
+
+// Derives would require `T: Copy`
+impl<T> Clone for Entry<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Entry<T> {}
+
+impl<'a> Batch<'a> {
+    pub(crate) fn new(argon: &'a Argon) -> Self {
+        Self {
+            argon,
+            ops: Vec::new(),
+        }
+    }
+
+    fn push<T>(&mut self, op: Op) -> Entry<T> {
+        self.ops.push(op);
+
+        Entry {
+            index: self.ops.len() - 1,
+            _value: PhantomData,
+        }
+    }
+
+    /// Queue a typed parameter read.
+    pub fn get<P>(&mut self) -> Entry<P::Value>
+    where
+        P: registry::Parameter,
+    {
+        self.push(Op::Read(P::ADDRESS))
+    }
+
+    /// Queue a typed parameter write.
+    ///
+    /// Values outside the parameter's valid range are rejected immediately.
+    pub fn set<P>(&mut self, value: P::Value) -> Result<Entry<()>, Error>
+    where
+        P: registry::Writable,
+    {
+        let raw = value.to_raw().ok_or(Error::Unrepresentable(P::NAME))?;
+
+        if !(P::MIN..=P::MAX).contains(&raw) {
+            return Err(Error::OutOfRange(P::NAME, raw, P::MIN, P::MAX));
+        }
+
+        Ok(self.push(Op::Write(P::ADDRESS, raw)))
+    }
+
+    /// Queue a parameter read by address.
+    pub fn read_raw(&mut self, address: u16) -> Entry<i32> {
+        self.push(Op::Read(address))
+    }
+
+    /// Queue a parameter write by address.
+    pub fn write_raw(&mut self, address: u16, value: i32) -> Entry<()> {
+        self.push(Op::Write(address, value))
+    }
+
+    /// Number of queued operations.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Whether no operations are queued.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Send all queued operations to the drive.
+    ///
+    /// An error is returned if the transaction itself failed, e.g. the drive didn't respond.
+    /// Failures of individual operations are reported in the results.
+    pub fn execute(self) -> Result<BatchResults, Error> {
+        let results = self
+            .argon
+            .transact(|handle| handle.execute(self.argon.address(), &self.ops))
+            .map_err(Error::Batch)?;
+
+        log::trace!("Executed batch {:?}. Results: {:?}", self.ops, results);
+
+        Ok(BatchResults {
+            ops: self.ops,
+            results,
+        })
+    }
+}
+
+/// Results of an executed [`Batch`].
+#[derive(Debug, Clone)]
+pub struct BatchResults {
+    ops: Vec<Op>,
+    results: Vec<Result<i32, StatusCode>>,
+}
+
+impl BatchResults {
+    /// Get the value read by a queued read.
+    pub fn get<T>(&self, entry: Entry<T>) -> Result<T, Error>
+    where
+        T: Value,
+    {
+        let address = self.ops[entry.index].address();
+
+        let raw = self.results[entry.index].map_err(|code| Error::ReadRaw(address, code))?;
+
+        T::from_raw(raw).ok_or(Error::InvalidValue(address, raw))
+    }
+
+    /// Get the status of a queued read or write.
+    pub fn status<T>(&self, entry: Entry<T>) -> StatusCode {
+        match self.results[entry.index] {
+            Ok(_) => StatusCode::Ok,
+            Err(code) => code,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        registry::{EncoderPpr, InputDivider, InputMultiplier},
+        sim::{Sim, SimDrive},
+        Argon, Error, StatusCode,
+    };
+
+    #[test]
+    fn mixed_results() {
+        let sim = Sim::new();
+        sim.add_drive(1, SimDrive::new());
+
+        let argon = Argon::with_sim(&sim, 1).unwrap();
+
+        let mut batch = argon.batch();
+
+        let write = batch.set::<InputMultiplier>(3).unwrap();
+        let read = batch.get::<InputMultiplier>();
+        let missing = batch.read_raw(1234);
+
+        // Split over more than one transaction
+        let reads = (0..10)
+            .map(|_| batch.get::<EncoderPpr>())
+            .collect::<Vec<_>>();
+
+        assert!(matches!(
+            batch.set::<InputDivider>(0),
+            Err(Error::OutOfRange(_, 0, _, _))
+        ));
+
+        let results = batch.execute().unwrap();
+
+        assert_eq!(results.status(write), StatusCode::Ok);
+        assert_eq!(results.get(read).unwrap(), 3);
+        assert_eq!(results.status(missing), StatusCode::ErrParameter);
+        assert!(matches!(
+            results.get(missing),
+            Err(Error::ReadRaw(1234, StatusCode::ErrParameter))
+        ));
+
+        for read in reads {
+            assert_eq!(results.get(read).unwrap(), 2000);
+        }
+    }
+}
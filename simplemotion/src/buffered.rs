@@ -0,0 +1,377 @@
+//! Stream position setpoints into the drive's motion buffer.
+//!
+//! Wraps the `smBuffered*` functions from `bufferedmotion.h`, so is only available with the `sys`
+//! backend.
+
+use crate::{Argon, Error, StatusCode};
+use simplemotion_sys::{
+    smBufferedAbort, smBufferedDeinit, smBufferedFillAndReceive, smBufferedGetFree,
+    smBufferedGetMaxFillSize, smBufferedInit, smBufferedRunAndSyncClocks, BufferedMotionAxis,
+};
+use std::{
+    collections::VecDeque,
+    iter::Peekable,
+    time::{Duration, Instant},
+};
+
+/// Buffered motion on a single drive.
+///
+/// Setpoints are executed by the drive at a fixed sample rate. For every setpoint sent, the value
+/// of a readback parameter is returned, e.g. the actual position.
+///
+/// Dropping a `BufferedMotion` aborts any motion left in the buffer. Call
+/// [`BufferedMotion::finish`] to let it complete first.
+///
+/// ```rust,no_run
+/// use simplemotion::{registry::{ActualPosition, Parameter}, Argon};
+/// use std::time::Duration;
+///
+/// let argon = Argon::connect("/dev/ttyUSB0", 1)?;
+///
+/// let mut motion = argon.buffered_motion(2500, ActualPosition::ADDRESS)?;
+///
+/// // One revolution of a 2000 PPR encoder over one second
+/// let setpoints = (0..2500).map(|i| i * 8000 / 2500);
+///
+/// for position in motion.stream(setpoints) {
+///     println!("Position {}", position?);
+/// }
+///
+/// motion.finish(Duration::from_secs(2))?;
+/// # Ok::<(), simplemotion::Error>(())
+/// ```
+pub struct BufferedMotion<'a> {
+    argon: &'a Argon,
+    axis: Box<BufferedMotionAxis>,
+
+    /// Whether the drive has started executing the buffer.
+    running: bool,
+}
+
+impl std::fmt::Debug for BufferedMotion<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BufferedMotion")
+            .field("address", &self.argon.address())
+            .field("running", &self.running)
+            .finish()
+    }
+}
+
+impl<'a> BufferedMotion<'a> {
+    pub(crate) fn new(argon: &'a Argon, sample_rate: u32, readback: u16) -> Result<Self, Error> {
+        let connection = argon
+            .bus()
+            .handle()
+            .connection()
+            .ok_or(Error::Unsupported("buffered motion on a simulated bus"))?;
+
+        // Initialised by `smBufferedInit`
+        let mut axis: Box<BufferedMotionAxis> = Box::new(unsafe { std::mem::zeroed() });
+
+        let handle = connection.handle();
+
+        check(unsafe {
+            smBufferedInit(
+                axis.as_mut(),
+                *handle,
+                argon.address(),
+                sample_rate as i32,
+                readback as i16,
+                crate::defs::SMPRET_32B as u8,
+            )
+        })?;
+
+        drop(handle);
+
+        Ok(Self {
+            argon,
+            axis,
+            running: false,
+        })
+    }
+
+    /// Run a C library function on the axis with the bus locked.
+    fn call<T>(&mut self, f: impl FnOnce(*mut BufferedMotionAxis) -> T) -> T {
+        // Checked in `new`
+        let connection = self.argon.bus().handle().connection().unwrap();
+
+        let _handle = connection.handle();
+
+        f(self.axis.as_mut())
+    }
+
+    /// Stream setpoints into the drive buffer, one per sample period.
+    ///
+    /// The returned iterator keeps the buffer filled and yields readback values as they arrive,
+    /// one per setpoint. It blocks while the buffer is full, and after the last setpoint is sent
+    /// until the remaining readback values have arrived.
+    pub fn stream<I>(&mut self, setpoints: I) -> Stream<'_, 'a, I::IntoIter>
+    where
+        I: IntoIterator<Item = i32>,
+    {
+        Stream {
+            motion: self,
+            pump: Pump::new(setpoints.into_iter()),
+        }
+    }
+
+    /// Wait for the drive to execute everything in the buffer, then leave buffered mode.
+    pub fn finish(mut self, timeout: Duration) -> Result<(), Error> {
+        let start = Instant::now();
+
+        loop {
+            let mut free = 0;
+
+            check(self.call(|axis| unsafe { smBufferedGetFree(axis, &mut free) }))?;
+
+            if free >= self.axis.bufferLength {
+                break;
+            }
+
+            if start.elapsed() > timeout {
+                return Err(Error::Timeout);
+            }
+
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        // Drop leaves buffered mode
+        Ok(())
+    }
+}
+
+/// Drive buffer operations used by [`Stream`].
+trait Buffer {
+    /// Send as many setpoints as fit in the drive buffer and collect the returned readback values,
+    /// of which up to `pending` are outstanding.
+    ///
+    /// Returns the number of setpoints sent.
+    fn fill(
+        &mut self,
+        setpoints: &mut impl Iterator<Item = i32>,
+        pending: usize,
+        received: &mut VecDeque<i32>,
+    ) -> Result<usize, Error>;
+
+    /// Whether no more readback values will arrive.
+    fn drained(&mut self) -> bool;
+}
+
+impl Buffer for BufferedMotion<'_> {
+    fn fill(
+        &mut self,
+        setpoints: &mut impl Iterator<Item = i32>,
+        pending: usize,
+        received: &mut VecDeque<i32>,
+    ) -> Result<usize, Error> {
+        let mut free = 0;
+
+        check(self.call(|axis| unsafe { smBufferedGetFree(axis, &mut free) }))?;
+
+        let max = self.call(|axis| unsafe { smBufferedGetMaxFillSize(axis, free) });
+
+        let mut points = setpoints.take(max.max(0) as usize).collect::<Vec<_>>();
+
+        // Nothing to send or receive
+        if points.is_empty() && pending == 0 {
+            return Ok(0);
+        }
+
+        // Readback values can arrive for setpoints sent in an earlier call
+        let mut readback = vec![0; points.len() + pending];
+        let mut num_received = 0;
+        let mut bytes_filled = 0;
+
+        check(self.call(|axis| unsafe {
+            smBufferedFillAndReceive(
+                axis,
+                points.len() as i32,
+                points.as_mut_ptr(),
+                &mut num_received,
+                readback.as_mut_ptr(),
+                &mut bytes_filled,
+            )
+        }))?;
+
+        received.extend(&readback[..num_received.clamp(0, readback.len() as i32) as usize]);
+
+        // Start executing once there's something in the buffer
+        if !self.running && !points.is_empty() {
+            check(self.call(|axis| unsafe { smBufferedRunAndSyncClocks(axis) }))?;
+
+            self.running = true;
+        }
+
+        Ok(points.len())
+    }
+
+    fn drained(&mut self) -> bool {
+        self.axis.numberOfPendingReadPackets == 0
+    }
+}
+
+impl Drop for BufferedMotion<'_> {
+    fn drop(&mut self) {
+        let result = self.call(|axis| unsafe {
+            smBufferedAbort(axis);
+            smBufferedDeinit(axis)
+        });
+
+        if let Err(e) = check(result) {
+            log::error!("Failed to leave buffered motion mode: {}", e);
+        }
+    }
+}
+
+/// Iterator returned by [`BufferedMotion::stream`].
+#[derive(Debug)]
+pub struct Stream<'m, 'a, I: Iterator<Item = i32>> {
+    motion: &'m mut BufferedMotion<'a>,
+    pump: Pump<I>,
+}
+
+impl<I> Iterator for Stream<'_, '_, I>
+where
+    I: Iterator<Item = i32>,
+{
+    type Item = Result<i32, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.pump.next(self.motion)
+    }
+}
+
+/// Sends setpoints to a [`Buffer`] and hands out one readback value per setpoint.
+#[derive(Debug)]
+struct Pump<I: Iterator<Item = i32>> {
+    setpoints: Peekable<I>,
+    received: VecDeque<i32>,
+
+    /// Number of setpoints sent.
+    sent: usize,
+
+    /// Number of readback values received, including those still in `received`.
+    readbacks: usize,
+
+    done: bool,
+}
+
+impl<I> Pump<I>
+where
+    I: Iterator<Item = i32>,
+{
+    fn new(setpoints: I) -> Self {
+        Self {
+            setpoints: setpoints.peekable(),
+            received: VecDeque::new(),
+            sent: 0,
+            readbacks: 0,
+            done: false,
+        }
+    }
+
+    fn next(&mut self, buffer: &mut impl Buffer) -> Option<Result<i32, Error>> {
+        loop {
+            if let Some(value) = self.received.pop_front() {
+                return Some(Ok(value));
+            }
+
+            if self.done {
+                return None;
+            }
+
+            let pending = self.sent - self.readbacks;
+
+            if self.setpoints.peek().is_none() && pending == 0 {
+                self.done = true;
+
+                continue;
+            }
+
+            let before = self.received.len();
+
+            match buffer.fill(&mut self.setpoints, pending, &mut self.received) {
+                Ok(sent) => {
+                    let received = self.received.len() - before;
+
+                    self.sent += sent;
+                    self.readbacks += received;
+
+                    if sent == 0 && received == 0 {
+                        // Every setpoint is sent and nothing else is coming
+                        if self.setpoints.peek().is_none() && buffer.drained() {
+                            log::warn!(
+                                "Buffered motion finished with {} readback values missing",
+                                pending
+                            );
+
+                            self.done = true;
+                        } else {
+                            // Buffer full or readback values in flight, wait for the drive
+                            std::thread::sleep(Duration::from_millis(1));
+                        }
+                    }
+                }
+                Err(e) => {
+                    self.done = true;
+
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+fn check(status: i32) -> Result<(), Error> {
+    let status = StatusCode::from(status);
+
+    if status.is_ok() {
+        Ok(())
+    } else {
+        Err(Error::Buffered(status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Buffer that returns the readback values for each fill on the next one.
+    #[derive(Default)]
+    struct Delayed {
+        in_flight: Vec<i32>,
+    }
+
+    impl Buffer for Delayed {
+        fn fill(
+            &mut self,
+            setpoints: &mut impl Iterator<Item = i32>,
+            pending: usize,
+            received: &mut VecDeque<i32>,
+        ) -> Result<usize, Error> {
+            assert!(self.in_flight.len() <= pending);
+
+            received.extend(self.in_flight.drain(..));
+
+            self.in_flight.extend(setpoints.take(3));
+
+            Ok(self.in_flight.len())
+        }
+
+        fn drained(&mut self) -> bool {
+            self.in_flight.is_empty()
+        }
+    }
+
+    #[test]
+    fn readback_per_setpoint() {
+        let mut buffer = Delayed::default();
+        let mut pump = Pump::new(0..10);
+
+        let readbacks = std::iter::from_fn(|| pump.next(&mut buffer))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(readbacks, (0..10).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,220 @@
+//! A bus shared by several drives.
+
+#[cfg(feature = "sim")]
+use crate::sim::Sim;
+use crate::{backend::Handle, parameters::Parameter, Argon, Error, StatusCode, Transport};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+
+/// An open SimpleMotion bus.
+///
+/// Several drives at different addresses can share one bus, e.g. a spindle and a few axes daisy
+/// chained from one USB adapter. Cloning a `Bus` is cheap and refers to the same underlying
+/// handle. The handle is closed when the last `Bus` and [`Argon`] using it are dropped.
+///
+/// ```rust
+/// use simplemotion::{
+///     sim::{Sim, SimDrive},
+///     Bus,
+/// };
+///
+/// let sim = Sim::new();
+/// sim.add_drive(1, SimDrive::new());
+/// sim.add_drive(2, SimDrive::new());
+///
+/// let bus = Bus::with_sim(&sim);
+///
+/// let spindle = bus.drive(1)?;
+/// let axis = bus.drive(2)?;
+///
+/// spindle.set_velocity_rps(10.0)?;
+/// axis.set_velocity_rps(-2.0)?;
+///
+/// // The bus stays open for `axis` after `bus` and `spindle` are dropped
+/// drop(bus);
+/// drop(spindle);
+///
+/// axis.status()?;
+/// # Ok::<(), simplemotion::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Bus {
+    handle: Arc<Handle>,
+
+    /// Number of times the bus has been reopened, so each drive on it knows when to read its
+    /// configuration again.
+    generation: Arc<AtomicU64>,
+}
+
+impl Bus {
+    /// Open the bus on the given device, e.g. `/dev/ttyUSB0`.
+    pub fn open(device: &str) -> Result<Self, Error> {
+        log::debug!("Open {}", device);
+
+        Handle::open(device)
+            .map(Self::from_handle)
+            .map_err(Error::OpenFailed)
+    }
+
+    /// Open the bus over a custom transport, e.g. a TCP socket or user provided serial port
+    /// wrapper.
+    pub fn with_transport<T>(transport: T) -> Result<Self, Error>
+    where
+        T: Transport + 'static,
+    {
+        log::debug!("Open custom transport");
+
+        Handle::with_transport(Arc::new(Mutex::new(transport)))
+            .map(Self::from_handle)
+            .map_err(Error::OpenFailed)
+    }
+
+    /// Use a simulated bus.
+    #[cfg(feature = "sim")]
+    pub fn with_sim(sim: &Sim) -> Self {
+        Self::from_handle(Handle::Sim(sim.clone()))
+    }
+
+    fn from_handle(handle: Handle) -> Self {
+        Self {
+            handle: Arc::new(handle),
+            generation: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Connect to the Argon drive at the given address on this bus.
+    pub fn drive(&self, address: u8) -> Result<Argon, Error> {
+        Argon::init(self.clone(), address)
+    }
+
+    pub(crate) fn handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    /// Probe each of the given addresses and return the devices that answered.
+    ///
+    /// Addresses with no device are skipped, including a device that stops responding part way
+    /// through being identified. Any other error, e.g. a communication error, ends the scan. Each
+    /// empty address takes up to the bus timeout of 100ms, so scanning the full `1..=255` range
+    /// takes a while on a sparsely populated bus.
+    ///
+    /// ```rust
+    /// use simplemotion::{
+    ///     sim::{Sim, SimDrive},
+    ///     Bus,
+    /// };
+    ///
+    /// let sim = Sim::new();
+    /// sim.add_drive(1, SimDrive::new());
+    /// sim.add_drive(4, SimDrive::new());
+    ///
+    /// let devices = Bus::with_sim(&sim).scan(1..=8)?;
+    ///
+    /// assert_eq!(devices.iter().map(|d| d.address).collect::<Vec<_>>(), [1, 4]);
+    /// # Ok::<(), simplemotion::Error>(())
+    /// ```
+    pub fn scan<A>(&self, addresses: A) -> Result<Vec<DeviceInfo>, Error>
+    where
+        A: IntoIterator<Item = u8>,
+    {
+        let mut devices = Vec::new();
+
+        for address in addresses {
+            match self.device_info(address) {
+                Ok(info) => {
+                    log::debug!("Found {:?}", info);
+
+                    devices.push(info);
+                }
+                Err(Error::ReadParameter(_, code)) if code.contains(StatusCode::ErrNodevice) => {
+                    log::trace!("No device at address {}: {}", address, code);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(devices)
+    }
+
+    /// Read the identification of the device at an address.
+    fn device_info(&self, address: u8) -> Result<DeviceInfo, Error> {
+        Ok(DeviceInfo {
+            address,
+            device_type: self.read_parameter(address, Parameter::DeviceType)? as u32,
+            firmware_version: self.read_parameter(address, Parameter::FirmwareVersion)? as u32,
+            serial_number: self.read_parameter(address, Parameter::SerialNumber)? as u32,
+            sm_version: self.read_parameter(address, Parameter::SmVersion)? as u32,
+        })
+    }
+
+    fn read_parameter(&self, address: u8, parameter: Parameter) -> Result<i32, Error> {
+        self.handle
+            .read_parameter(address, parameter as u16)
+            .map_err(|code| Error::ReadParameter(parameter, code))
+    }
+
+    /// Close the bus and open it again.
+    ///
+    /// This affects every drive on the bus. Each [`Argon`] on it reads its drive configuration
+    /// again before its next transaction.
+    pub fn reopen(&self) -> Result<(), Error> {
+        self.handle.reopen().map_err(Error::OpenFailed)?;
+
+        self.generation.fetch_add(1, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Number of times the bus has been reopened.
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+}
+
+/// A device found by [`Bus::scan`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceInfo {
+    /// Bus address.
+    pub address: u8,
+
+    /// Device type code.
+    pub device_type: u32,
+
+    /// Firmware version.
+    pub firmware_version: u32,
+
+    /// Device serial number.
+    pub serial_number: u32,
+
+    /// SimpleMotion protocol version implemented by the device.
+    pub sm_version: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{Sim, SimDrive};
+
+    #[test]
+    fn scan_errors() {
+        let sim = Sim::new();
+        sim.add_drive(2, SimDrive::new());
+
+        let bus = Bus::with_sim(&sim);
+
+        // Only a missing device is skipped
+        sim.fail_transactions(1);
+
+        assert!(matches!(
+            bus.scan(1..=3),
+            Err(Error::ReadParameter(_, StatusCode::ErrCommunication))
+        ));
+
+        let devices = bus.scan(1..=3).unwrap();
+
+        assert_eq!(devices.iter().map(|d| d.address).collect::<Vec<_>>(), [2]);
+    }
+}
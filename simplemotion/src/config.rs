@@ -0,0 +1,365 @@
+//! Drive configuration snapshots.
+
+use crate::{
+    registry::{self, Info},
+    DeviceInfo, Error,
+};
+use std::{collections::BTreeMap, fmt};
+
+/// Parameters saved in a [`DriveConfig`], in the order they are applied.
+///
+/// The persistent settings typed in the [registry](crate::registry). This is not every setting
+/// the drive stores: see [`DriveConfig`]. Left out are setpoints, the control mode, commands,
+/// fault and status bits, readouts, device identification, and bus settings that would break
+/// communication if restored to another drive.
+pub(crate) const SETTINGS: &[Info] = &[
+    // Scaling
+    Info::of::<registry::InputMultiplier>(),
+    Info::of::<registry::InputDivider>(),
+    // Motor and feedback
+    Info::of::<registry::MotorType>(),
+    Info::of::<registry::MotorPolePairs>(),
+    Info::of::<registry::MotorTorqueConstant>(),
+    Info::of::<registry::MotorResistance>(),
+    Info::of::<registry::MotorInductance>(),
+    Info::of::<registry::MotorInertia>(),
+    Info::of::<registry::FeedbackDevice>(),
+    Info::of::<registry::EncoderPpr>(),
+    Info::of::<registry::ContinuousCurrentLimit>(),
+    Info::of::<registry::PeakCurrentLimit>(),
+    // Control loop gains
+    Info::of::<registry::PositionP>(),
+    Info::of::<registry::PositionFeedForward>(),
+    Info::of::<registry::VelocityP>(),
+    Info::of::<registry::VelocityI>(),
+    Info::of::<registry::VelocityFeedForward>(),
+    Info::of::<registry::AccelerationFeedForward>(),
+    Info::of::<registry::TorqueP>(),
+    Info::of::<registry::TorqueI>(),
+    // Homing and trajectory planner
+    Info::of::<registry::TrajPlannerHomingOffset>(),
+    Info::of::<registry::TrajPlannerAccel>(),
+    Info::of::<registry::TrajPlannerVel>(),
+    // Fault limits
+    Info::of::<registry::FollowErrorLimit>(),
+    Info::of::<registry::OverspeedFaultLimit>(),
+    Info::of::<registry::FaultBehavior>(),
+];
+
+/// Snapshot of a drive's identity and settings.
+///
+/// Read with [`Argon::config`](crate::Argon::config). Includes the scaling, motor and encoder
+/// setup, current limits, control loop gains, trajectory planner limits and fault limits.
+///
+/// Only parameters defined in the vendored `simplemotion_defs.h` are known to this crate. Drive
+/// settings missing from it, e.g. IO configuration, brake control or settings added by newer
+/// firmware, are not included and need to be copied with Granity or
+/// [`Argon::read_raw`](crate::Argon::read_raw) and [`Argon::write_raw`](crate::Argon::write_raw).
+///
+/// ```rust
+/// use simplemotion::{
+///     sim::{Sim, SimDrive},
+///     Argon,
+/// };
+///
+/// let sim = Sim::new();
+/// sim.add_drive(1, SimDrive::new());
+///
+/// let argon = Argon::with_sim(&sim, 1)?;
+/// let config = argon.config()?;
+///
+/// assert_eq!(config.parameters["SMP_ENCODER_PPR"], 2000);
+/// # Ok::<(), simplemotion::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DriveConfig {
+    /// The drive the snapshot was read from.
+    pub device: DeviceInfo,
+
+    /// Raw parameter values, keyed by `SMP_*` name.
+    pub parameters: BTreeMap<String, i32>,
+}
+
+impl DriveConfig {
+    /// Check every parameter is a known setting with a valid value, and return them in
+    /// [`SETTINGS`] order.
+    pub(crate) fn validate(&self) -> Result<Vec<(Info, i32)>, Error> {
+        let mut settings = self
+            .parameters
+            .iter()
+            .map(|(name, value)| {
+                let info = SETTINGS
+                    .iter()
+                    .find(|info| info.name == name)
+                    .ok_or_else(|| Error::UnknownParameter(name.clone()))?;
+
+                if !info.contains(*value) {
+                    return Err(Error::OutOfRange(info.name, *value, info.min, info.max));
+                }
+
+                Ok((*info, *value))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        settings.sort_by_key(|(info, _)| SETTINGS.iter().position(|setting| setting == info));
+
+        Ok(settings)
+    }
+}
+
+/// A setting that differs between a drive and a [`DriveConfig`].
+///
+/// Returned by [`Argon::apply_config`](crate::Argon::apply_config).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ConfigChange {
+    pub parameter: Info,
+
+    /// Value on the drive before the change.
+    pub current: i32,
+
+    /// Value from the configuration.
+    pub new: i32,
+}
+
+impl fmt::Display for ConfigChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.parameter.name, self.current, self.new
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        defs::{SMP_CONTINUOUS_CURRENT_LIMIT, SMP_ENCODER_PPR, SMP_TRAJ_PLANNER_VEL},
+        sim::{Sim, SimDrive},
+        Argon,
+    };
+    #[cfg(feature = "serde")]
+    use crate::{ControlMode, Faults, Status, StatusCode};
+
+    #[test]
+    fn apply_config() {
+        let sim = Sim::new();
+        sim.add_drive(1, SimDrive::new());
+        sim.add_drive(
+            2,
+            SimDrive::new().with_parameter(SMP_ENCODER_PPR as u16, 1000),
+        );
+
+        let old = Argon::with_sim(&sim, 1).unwrap();
+        let new = Argon::with_sim(&sim, 2).unwrap();
+
+        let config = old.config().unwrap();
+
+        // Gains, motor and encoder setup and fault limits are included, bus settings aren't
+        for name in [
+            "SMP_VEL_P",
+            "SMP_MOTOR_TYPE",
+            "SMP_FB1_DEVICE_SELECTION",
+            "SMP_OVERSPEED_FAULT_LIMIT",
+        ] {
+            assert!(config.parameters.contains_key(name), "{} missing", name);
+        }
+
+        assert!(!config.parameters.contains_key("SMP_BUS_SPEED"));
+
+        let saves = || sim.with_drive(2, |drive| drive.saves()).unwrap();
+
+        // Dry run only reports the difference
+        let changes = new.apply_config(&config, true).unwrap();
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].to_string(), "SMP_ENCODER_PPR: 1000 -> 2000");
+        assert_eq!(new.scaling().counts_per_rev(), 4000.0);
+        assert_eq!(saves(), 0);
+
+        assert!(new.status().unwrap().enabled());
+
+        new.apply_config(&config, false).unwrap();
+
+        assert_eq!(new.config().unwrap().parameters, config.parameters);
+        assert_eq!(new.scaling().counts_per_rev(), 8000.0);
+        assert_eq!(saves(), 1);
+        assert!(!new.status().unwrap().enabled());
+        assert!(new.apply_config(&config, true).unwrap().is_empty());
+
+        // Nothing to save if nothing changed
+        new.apply_config(&config, false).unwrap();
+
+        assert_eq!(saves(), 1);
+
+        // Nothing is written if any value is invalid
+        let mut invalid = config.clone();
+        invalid
+            .parameters
+            .insert("SMP_CONTINUOUS_CURRENT_LIMIT".to_string(), 1000);
+        invalid
+            .parameters
+            .insert("SMP_INPUT_DIVIDER".to_string(), 0);
+
+        assert!(matches!(
+            new.apply_config(&invalid, false),
+            Err(Error::OutOfRange("SMP_INPUT_DIVIDER", 0, 1, _))
+        ));
+        assert!(new.apply_config(&config, true).unwrap().is_empty());
+
+        invalid = config.clone();
+        invalid.parameters.insert("SMP_ENCODER_PPR".to_string(), 0);
+
+        assert!(matches!(
+            new.apply_config(&invalid, false),
+            Err(Error::OutOfRange("SMP_ENCODER_PPR", 0, 1, _))
+        ));
+
+        invalid.parameters.clear();
+        invalid.parameters.insert("SMP_BUS_SPEED".to_string(), 9600);
+
+        assert!(matches!(
+            new.apply_config(&invalid, false),
+            Err(Error::UnknownParameter(name)) if name == "SMP_BUS_SPEED"
+        ));
+    }
+
+    #[test]
+    fn apply_config_drive_limits() {
+        let sim = Sim::new();
+        sim.add_drive(1, SimDrive::new());
+        sim.add_drive(
+            2,
+            SimDrive::new()
+                .with_parameter(SMP_ENCODER_PPR as u16, 1000)
+                .with_parameter(SMP_CONTINUOUS_CURRENT_LIMIT as u16, 2000)
+                .with_range(SMP_CONTINUOUS_CURRENT_LIMIT as u16, 0, 3000),
+        );
+
+        let config = Argon::with_sim(&sim, 1).unwrap().config().unwrap();
+        let argon = Argon::with_sim(&sim, 2).unwrap();
+
+        // The drive's own limits are checked before anything is written
+        assert!(matches!(
+            argon.apply_config(&config, false),
+            Err(Error::OutOfRange(
+                "SMP_CONTINUOUS_CURRENT_LIMIT",
+                5000,
+                0,
+                3000
+            ))
+        ));
+        assert_eq!(argon.read_raw(SMP_ENCODER_PPR as u16).unwrap(), 1000);
+        assert!(argon.status().unwrap().enabled());
+    }
+
+    #[test]
+    fn apply_config_restores_on_failure() {
+        let sim = Sim::new();
+        sim.add_drive(1, SimDrive::new());
+        sim.add_drive(
+            2,
+            SimDrive::new()
+                .with_parameter(SMP_ENCODER_PPR as u16, 1000)
+                .with_parameter(SMP_TRAJ_PLANNER_VEL as u16, 500)
+                .reject_writes(SMP_TRAJ_PLANNER_VEL as u16),
+        );
+
+        let config = Argon::with_sim(&sim, 1).unwrap().config().unwrap();
+        let argon = Argon::with_sim(&sim, 2).unwrap();
+
+        // The encoder is written before the trajectory planner, then restored when it fails
+        assert!(matches!(
+            argon.apply_config(&config, false),
+            Err(Error::SetRaw(address, 1000, _)) if address == SMP_TRAJ_PLANNER_VEL as u16
+        ));
+        assert_eq!(argon.read_raw(SMP_ENCODER_PPR as u16).unwrap(), 1000);
+        assert_eq!(argon.scaling().counts_per_rev(), 4000.0);
+        assert_eq!(sim.with_drive(2, |drive| drive.saves()), Some(0));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn export_import() {
+        let sim = Sim::new();
+        sim.add_drive(1, SimDrive::new());
+        sim.add_drive(
+            2,
+            SimDrive::new().with_parameter(SMP_TRAJ_PLANNER_VEL as u16, 500),
+        );
+
+        let path =
+            std::env::temp_dir().join(format!("simplemotion-config-{}.json", std::process::id()));
+
+        let config = Argon::with_sim(&sim, 1)
+            .unwrap()
+            .export_config(&path)
+            .unwrap();
+
+        let argon = Argon::with_sim(&sim, 2).unwrap();
+        let changes = argon.import_config(&path, true).unwrap();
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!((changes[0].current, changes[0].new), (500, 1000));
+
+        argon.import_config(&path, false).unwrap();
+
+        assert_eq!(argon.config().unwrap().parameters, config.parameters);
+        assert_eq!(sim.with_drive(2, |drive| drive.saves()), Some(1));
+
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            argon.import_config(&path, true),
+            Err(Error::ConfigFile(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_round_trip() {
+        let config = DriveConfig {
+            device: DeviceInfo {
+                address: 1,
+                device_type: 2,
+                firmware_version: 3,
+                serial_number: 4,
+                sm_version: 5,
+            },
+            parameters: [("SMP_ENCODER_PPR".to_string(), 2000)]
+                .into_iter()
+                .collect(),
+        };
+
+        let json = serde_json::to_string(&config).unwrap();
+
+        assert_eq!(serde_json::from_str::<DriveConfig>(&json).unwrap(), config);
+
+        let faults = Faults::OVERVOLTAGE | Faults::HOST_COMM_ERROR;
+        let json = serde_json::to_string(&faults).unwrap();
+
+        assert_eq!(json, "\"OVERVOLTAGE | HOST_COMM_ERROR\"");
+        assert_eq!(serde_json::from_str::<Faults>(&json).unwrap(), faults);
+
+        let status = Status::from(Status::ENABLED.bits() | 1 << 30);
+        let json = serde_json::to_string(&status).unwrap();
+
+        assert_eq!(serde_json::from_str::<Status>(&json).unwrap(), status);
+
+        let code = StatusCode::from(-1);
+        let json = serde_json::to_string(&code).unwrap();
+
+        assert_eq!(json, "-1");
+        assert_eq!(serde_json::from_str::<StatusCode>(&json).unwrap(), code);
+
+        let json = serde_json::to_string(&ControlMode::Position).unwrap();
+
+        assert_eq!(json, "\"Position\"");
+        assert_eq!(
+            serde_json::from_str::<ControlMode>(&json).unwrap(),
+            ControlMode::Position
+        );
+    }
+}
@@ -0,0 +1,5 @@
+//! SimpleMotionV2 constants, generated from `simplemotion_defs.h` by `build.rs`.
+
+#![allow(dead_code)]
+
+include!(concat!(env!("OUT_DIR"), "/defs.rs"));
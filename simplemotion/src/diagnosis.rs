@@ -0,0 +1,181 @@
+//! Fault explanations and remediation hints.
+
+use crate::{
+    registry::{self, Info},
+    Faults,
+};
+use std::fmt;
+
+/// Explanation of a single drive fault, with a suggested fix.
+///
+/// Created by [`Faults::diagnose`], or by [`Argon::diagnose_faults`](crate::Argon::diagnose_faults)
+/// which also reads the related parameters from the drive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnosis {
+    /// The fault, with exactly one flag set, or the unknown bits.
+    pub fault: Faults,
+
+    /// What the fault means.
+    pub explanation: &'static str,
+
+    /// What to check or change to fix it.
+    pub remedy: &'static str,
+
+    /// Parameters worth checking for this fault.
+    pub related: &'static [Info],
+
+    /// Raw values of the related parameters, if they were read from the drive.
+    pub readings: Vec<(Info, i32)>,
+}
+
+impl Diagnosis {
+    pub(crate) fn new(fault: Faults) -> Self {
+        let (explanation, remedy, related) = describe(fault);
+
+        Self {
+            fault,
+            explanation,
+            remedy,
+            related,
+            readings: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} {}", self.fault, self.explanation, self.remedy)?;
+
+        for (info, value) in &self.readings {
+            write!(f, " [{} = {} ({:?})]", info.name, value, info.unit)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Explanation, remedy and related parameters of each fault.
+const HINTS: &[(Faults, &str, &str, &[Info])] = &[
+    (
+        Faults::FOLLOWERROR,
+        "The position error exceeded the following error limit.",
+        "Tune the servo loop, reduce acceleration or load, or raise the limit if it is too tight.",
+        &[Info::of::<registry::FollowErrorLimit>()],
+    ),
+    (
+        Faults::OVERCURRENT,
+        "Motor current exceeded the drive or motor limit.",
+        "Check the motor wiring for shorts, reduce the load, and check the current limits and torque loop tuning.",
+        &[
+            Info::of::<registry::ContinuousCurrentLimit>(),
+            Info::of::<registry::PeakCurrentLimit>(),
+        ],
+    ),
+    (
+        Faults::COMMUNICATION,
+        "Communication between the drive and another device on the bus failed.",
+        "Check bus cabling, termination and grounding, and that no two devices share an address.",
+        &[],
+    ),
+    (
+        Faults::ENCODER,
+        "The encoder signal was lost or invalid.",
+        "Check the encoder cable, connector and shielding, and that the encoder type and resolution are configured correctly.",
+        &[Info::of::<registry::EncoderPpr>()],
+    ),
+    (
+        Faults::OVERTEMP,
+        "The drive or motor is too hot.",
+        "Improve cooling or airflow, reduce the duty cycle, and check the continuous current limit.",
+        &[
+            Info::of::<registry::DeviceTemperature>(),
+            Info::of::<registry::ContinuousCurrentLimit>(),
+        ],
+    ),
+    (
+        Faults::UNDERVOLTAGE,
+        "The DC bus voltage dropped below the minimum.",
+        "Check the power supply rating and wiring, and that it can supply peak current without sagging.",
+        &[Info::of::<registry::ActualBusVoltage>()],
+    ),
+    (
+        Faults::OVERVOLTAGE,
+        "The DC bus voltage rose above the maximum, usually from energy returned while braking.",
+        "Fit or check a braking resistor or shunt regulator, or reduce deceleration.",
+        &[Info::of::<registry::ActualBusVoltage>()],
+    ),
+    (
+        Faults::PROGRAM_OR_MEM,
+        "The drive firmware hit an internal error, ran out of memory or the command queue overflowed.",
+        "Send buffered commands more slowly. If it persists, power cycle the drive and update its firmware.",
+        &[Info::of::<registry::BufferFreeBytes>()],
+    ),
+    (
+        Faults::HARDWARE,
+        "The drive detected a hardware failure.",
+        "Power cycle the drive. If it persists, the drive may need repair.",
+        &[],
+    ),
+    (
+        Faults::OVERVELOCITY,
+        "The motor exceeded the velocity limit.",
+        "Check the velocity limit and setpoint scaling, and tune the velocity loop to reduce overshoot.",
+        &[
+            Info::of::<registry::TrajPlannerVel>(),
+            Info::of::<registry::InputMultiplier>(),
+            Info::of::<registry::InputDivider>(),
+        ],
+    ),
+    (
+        Faults::INIT,
+        "Drive initialisation failed.",
+        "Check the drive configuration and power cycle the drive.",
+        &[],
+    ),
+    (
+        Faults::MOTION,
+        "Motion failed, e.g. homing or commutation sensing didn't complete.",
+        "Check homing switches and settings, and that the motor can move freely.",
+        &[],
+    ),
+    (
+        Faults::RANGE,
+        "A setpoint or position was outside the allowed range.",
+        "Check the setpoint scaling and the position limits.",
+        &[
+            Info::of::<registry::InputMultiplier>(),
+            Info::of::<registry::InputDivider>(),
+        ],
+    ),
+    (
+        Faults::PSTAGE_FORCED_OFF,
+        "The power stage was forced off, e.g. by the enable input or safe torque off.",
+        "Check the enable and STO inputs and their wiring.",
+        &[],
+    ),
+    (
+        Faults::HOST_COMM_ERROR,
+        "The host stopped communicating within the communication timeout.",
+        "Check the host control loop is running and the bus connection, or raise the timeout.",
+        &[Info::of::<registry::Timeout>()],
+    ),
+    (
+        Faults::CONFIG,
+        "The drive configuration is invalid.",
+        "Review the drive settings, e.g. with Granity, and save a valid configuration.",
+        &[],
+    ),
+];
+
+/// Explanation, remedy and related parameters of a single fault.
+fn describe(fault: Faults) -> (&'static str, &'static str, &'static [Info]) {
+    HINTS
+        .iter()
+        .find(|(flag, ..)| *flag == fault)
+        .map(|(_, explanation, remedy, related)| (*explanation, *remedy, *related))
+        .unwrap_or((
+            "Fault bits not known to this library.",
+            "See the drive documentation.",
+            &[],
+        ))
+}
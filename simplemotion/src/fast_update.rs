@@ -0,0 +1,211 @@
+//! Fast update cycle: exchange a setpoint for feedback in one short packet.
+//!
+//! The layout of the two 16 bit words sent and received depends on `SMP_FAST_UPDATE_CYCLE_FORMAT`
+//! and follows `FastUpdateCycleWriteData`/`FastUpdateCycleReadData` in `simplemotion.h`:
+//!
+//! | Format    | Sent                                 | Received                          |
+//! | --------- | ------------------------------------ | --------------------------------- |
+//! | `Default` | 16 bit setpoint, control bits        | 16 bit position, status bits 0-15 |
+//! | `Alt1`    | 28 bit setpoint, 4 bit control bits  | 30 bit position, enabled, fault   |
+//!
+//! Control bit 0 enables the drive and is always set.
+
+use crate::{
+    defs::{FAST_UPDATE_CYCLE_FORMAT_ALT1, FAST_UPDATE_CYCLE_FORMAT_DEFAULT},
+    Status,
+};
+
+/// Control bit: enable the drive.
+pub(crate) const CONTROL_ENABLE: u32 = 1 << 0;
+
+/// Data format used by [`Argon::fast_update`](crate::Argon::fast_update).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum FastUpdateFormat {
+    /// 16 bit setpoint, 16 bit position and status feedback.
+    #[default]
+    Default = FAST_UPDATE_CYCLE_FORMAT_DEFAULT as isize,
+
+    /// 28 bit setpoint, 30 bit position feedback plus enabled and fault flags.
+    Alt1 = FAST_UPDATE_CYCLE_FORMAT_ALT1 as isize,
+}
+
+impl FastUpdateFormat {
+    #[cfg(feature = "sim")]
+    pub(crate) fn from_raw(raw: i32) -> Option<Self> {
+        match raw as u32 {
+            FAST_UPDATE_CYCLE_FORMAT_DEFAULT => Some(Self::Default),
+            FAST_UPDATE_CYCLE_FORMAT_ALT1 => Some(Self::Alt1),
+            _ => None,
+        }
+    }
+
+    /// Range of setpoints that can be sent in this format.
+    pub fn setpoint_range(&self) -> (i32, i32) {
+        match self {
+            Self::Default => (i16::MIN.into(), i16::MAX.into()),
+            Self::Alt1 => (-(1 << 27), (1 << 27) - 1),
+        }
+    }
+
+    /// Encode a setpoint and control bits into the words sent to the drive.
+    pub(crate) fn encode_command(&self, setpoint: i32, control: u32) -> (u16, u16) {
+        match self {
+            Self::Default => (setpoint as u16, control as u16),
+            Self::Alt1 => split((setpoint as u32 & 0x0fff_ffff) | (control << 28)),
+        }
+    }
+
+    /// Decode the words sent to the drive into a setpoint and control bits.
+    #[cfg(feature = "sim")]
+    pub(crate) fn decode_command(&self, out1: u16, out2: u16) -> (i32, u32) {
+        match self {
+            Self::Default => (i32::from(out1 as i16), u32::from(out2)),
+            Self::Alt1 => {
+                let word = join(out1, out2);
+
+                (sign_extend(word, 28), word >> 28)
+            }
+        }
+    }
+
+    /// Encode position and status feedback into the words returned by the drive.
+    #[cfg(feature = "sim")]
+    pub(crate) fn encode_feedback(&self, position: i32, status: u32) -> (u16, u16) {
+        match self {
+            Self::Default => (position as u16, status as u16),
+            Self::Alt1 => {
+                let status = Status::from(status);
+
+                split(
+                    (position as u32 & 0x3fff_ffff)
+                        | (u32::from(status.enabled()) << 30)
+                        | (u32::from(status.faultstop()) << 31),
+                )
+            }
+        }
+    }
+
+    /// Decode the words returned by the drive.
+    pub(crate) fn decode_feedback(&self, in1: u16, in2: u16) -> FastFeedback {
+        match self {
+            Self::Default => {
+                let status = Status::from(u32::from(in2));
+
+                FastFeedback {
+                    position: i32::from(in1 as i16),
+                    enabled: status.enabled(),
+                    fault: status.faultstop(),
+                    status: Some(status),
+                }
+            }
+            Self::Alt1 => {
+                let word = join(in1, in2);
+
+                FastFeedback {
+                    position: sign_extend(word, 30),
+                    enabled: word & (1 << 30) != 0,
+                    fault: word & (1 << 31) != 0,
+                    status: None,
+                }
+            }
+        }
+    }
+}
+
+/// Feedback returned by [`Argon::fast_update`](crate::Argon::fast_update).
+#[derive(Debug)]
+pub struct FastFeedback {
+    /// Position feedback in encoder counts.
+    ///
+    /// Only the lower 16 (`Default`) or 30 (`Alt1`) bits are sent, so this wraps around.
+    pub position: i32,
+
+    /// The drive is enabled.
+    pub enabled: bool,
+
+    /// The drive is stopped because of a fault.
+    pub fault: bool,
+
+    /// Lower 16 bits of the drive status. Only available in the `Default` format.
+    pub status: Option<Status>,
+}
+
+fn split(word: u32) -> (u16, u16) {
+    (word as u16, (word >> 16) as u16)
+}
+
+fn join(low: u16, high: u16) -> u32 {
+    u32::from(low) | (u32::from(high) << 16)
+}
+
+/// Sign extend the lowest `bits` bits of `value`.
+fn sign_extend(value: u32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+
+    ((value << shift) as i32) >> shift
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defs::{STAT_ENABLED, STAT_FAULTSTOP};
+    use crate::{sim::setup, ControlMode, Error};
+    use std::time::Duration;
+
+    #[test]
+    fn round_trip() {
+        for format in [FastUpdateFormat::Default, FastUpdateFormat::Alt1] {
+            let (out1, out2) = format.encode_command(-1234, CONTROL_ENABLE);
+
+            assert_eq!(format.decode_command(out1, out2), (-1234, CONTROL_ENABLE));
+
+            let (in1, in2) = format.encode_feedback(-5678, STAT_ENABLED | STAT_FAULTSTOP);
+            let feedback = format.decode_feedback(in1, in2);
+
+            assert_eq!(feedback.position, -5678);
+            assert!(feedback.enabled);
+            assert!(feedback.fault);
+        }
+    }
+
+    #[test]
+    fn position_wraps() {
+        let (in1, in2) = FastUpdateFormat::Default.encode_feedback(40_000, 0);
+
+        assert_eq!(
+            FastUpdateFormat::Default.decode_feedback(in1, in2).position,
+            40_000 - 65_536
+        );
+
+        let (in1, in2) = FastUpdateFormat::Alt1.encode_feedback(40_000, 0);
+
+        assert_eq!(
+            FastUpdateFormat::Alt1.decode_feedback(in1, in2).position,
+            40_000
+        );
+    }
+
+    #[test]
+    fn fast_update() {
+        let (sim, mut argon) = setup();
+
+        argon.set_control_mode(ControlMode::Position).unwrap();
+        argon
+            .set_fast_update_format(FastUpdateFormat::Alt1)
+            .unwrap();
+
+        argon.fast_update(100_000).unwrap();
+        sim.advance(Duration::from_millis(500));
+
+        let feedback = argon.fast_update(100_000).unwrap();
+
+        assert!((feedback.position - 100_000).abs() < 10);
+        assert!(feedback.enabled);
+        assert!(!feedback.fault);
+
+        assert!(matches!(
+            argon.fast_update(1 << 30),
+            Err(Error::OutOfRange(..))
+        ));
+    }
+}
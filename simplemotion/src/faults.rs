@@ -1,70 +1,167 @@
-use simplemotion_sys::{
-    FLT_COMMUNICATION, FLT_CONFIG, FLT_ENCODER, FLT_FOLLOWERROR, FLT_HARDWARE, FLT_HOST_COMM_ERROR,
-    FLT_INIT, FLT_MOTION, FLT_OVERCURRENT, FLT_OVERTEMP, FLT_OVERVELOCITY, FLT_OVERVOLTAGE,
-    FLT_PROGRAM_OR_MEM, FLT_PSTAGE_FORCED_OFF, FLT_RANGE, FLT_UNDERVOLTAGE,
+use crate::defs::{
+    FLT_ALLOC, FLT_COMMUNICATION, FLT_CONFIG, FLT_ENCODER, FLT_FIRMWARE, FLT_FOLLOWERROR,
+    FLT_GC_COMM, FLT_HARDWARE, FLT_HOST_COMM_ERROR, FLT_INIT, FLT_MOTION, FLT_OVERCURRENT,
+    FLT_OVERTEMP, FLT_OVERVELOCITY, FLT_OVERVOLTAGE, FLT_PROGRAM_OR_MEM, FLT_PSTAGE_FORCED_OFF,
+    FLT_QUEUE_FULL, FLT_RANGE, FLT_SM485_ERROR, FLT_UNDERVOLTAGE,
 };
+use crate::Diagnosis;
+use std::fmt;
 
-/// Drive faults.
-#[derive(Debug)]
-pub struct Faults {
-    pub followerror: bool,
-    pub overcurrent: bool,
-    pub communication: bool,
-    pub encoder: bool,
-    pub overtemp: bool,
-    pub undervoltage: bool,
-    pub overvoltage: bool,
-    pub program_or_mem: bool,
-    pub hardware: bool,
-    pub overvelocity: bool,
-    pub init: bool,
-    pub motion: bool,
-    pub range: bool,
-    pub pstage_forced_off: bool,
-    pub host_comm_error: bool,
-    pub config: bool,
+bitflags::bitflags! {
+    /// Drive faults, read from `SMP_FAULTS`.
+    ///
+    /// ```rust
+    /// use simplemotion::Faults;
+    ///
+    /// let faults = Faults::OVERVOLTAGE | Faults::HOST_COMM_ERROR;
+    ///
+    /// assert!(faults.overvoltage());
+    /// assert_eq!(Faults::from(faults.to_bits()), faults);
+    /// assert_eq!(faults.to_string(), "OVERVOLTAGE, HOST_COMM_ERROR");
+    /// ```
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct Faults: u32 {
+        const FOLLOWERROR = FLT_FOLLOWERROR;
+        const OVERCURRENT = FLT_OVERCURRENT;
+        const COMMUNICATION = FLT_COMMUNICATION;
+        const ENCODER = FLT_ENCODER;
+        const OVERTEMP = FLT_OVERTEMP;
+        const UNDERVOLTAGE = FLT_UNDERVOLTAGE;
+        const OVERVOLTAGE = FLT_OVERVOLTAGE;
+        const PROGRAM_OR_MEM = FLT_PROGRAM_OR_MEM;
+        const HARDWARE = FLT_HARDWARE;
+        const OVERVELOCITY = FLT_OVERVELOCITY;
+        const INIT = FLT_INIT;
+        const MOTION = FLT_MOTION;
+        const RANGE = FLT_RANGE;
+        const PSTAGE_FORCED_OFF = FLT_PSTAGE_FORCED_OFF;
+        const HOST_COMM_ERROR = FLT_HOST_COMM_ERROR;
+        const CONFIG = FLT_CONFIG;
+
+        // IO side names for the same bits. Declared last so iteration and `Display` use the
+        // drive side names.
+
+        /// Same bit as [`Faults::HOST_COMM_ERROR`].
+        const GC_COMM = FLT_GC_COMM;
+        /// Same bit as [`Faults::PROGRAM_OR_MEM`].
+        const QUEUE_FULL = FLT_QUEUE_FULL;
+        /// Same bit as [`Faults::COMMUNICATION`].
+        const SM485_ERROR = FLT_SM485_ERROR;
+        /// Non-recoverable program error. Same bit as [`Faults::PROGRAM_OR_MEM`].
+        const FIRMWARE = FLT_FIRMWARE;
+        /// Memory allocation failed. Same bit as [`Faults::PROGRAM_OR_MEM`].
+        const ALLOC = FLT_ALLOC;
+    }
 }
 
 impl Faults {
+    flag_accessors! {
+        followerror => FOLLOWERROR,
+        overcurrent => OVERCURRENT,
+        communication => COMMUNICATION,
+        encoder => ENCODER,
+        overtemp => OVERTEMP,
+        undervoltage => UNDERVOLTAGE,
+        overvoltage => OVERVOLTAGE,
+        program_or_mem => PROGRAM_OR_MEM,
+        hardware => HARDWARE,
+        overvelocity => OVERVELOCITY,
+        init => INIT,
+        motion => MOTION,
+        range => RANGE,
+        pstage_forced_off => PSTAGE_FORCED_OFF,
+        host_comm_error => HOST_COMM_ERROR,
+        config => CONFIG,
+    }
+
+    /// Any fault is set.
     pub fn any(&self) -> bool {
-        self.followerror
-            || self.overcurrent
-            || self.communication
-            || self.encoder
-            || self.overtemp
-            || self.undervoltage
-            || self.overvoltage
-            || self.program_or_mem
-            || self.hardware
-            || self.overvelocity
-            || self.init
-            || self.motion
-            || self.range
-            || self.pstage_forced_off
-            || self.host_comm_error
-            || self.config
+        !self.is_empty()
+    }
+
+    /// Raw `SMP_FAULTS` value, including any bits without a name.
+    pub fn to_bits(&self) -> u32 {
+        self.bits()
+    }
+
+    /// Explain each active fault and suggest how to fix it.
+    ///
+    /// Use [`Argon::diagnose_faults`](crate::Argon::diagnose_faults) to also read the related
+    /// parameters from the drive.
+    pub fn diagnose(&self) -> Vec<Diagnosis> {
+        self.iter().map(Diagnosis::new).collect()
     }
 }
 
 impl From<u32> for Faults {
     fn from(other: u32) -> Self {
-        Self {
-            followerror: other & FLT_FOLLOWERROR > 0,
-            overcurrent: other & FLT_OVERCURRENT > 0,
-            communication: other & FLT_COMMUNICATION > 0,
-            encoder: other & FLT_ENCODER > 0,
-            overtemp: other & FLT_OVERTEMP > 0,
-            undervoltage: other & FLT_UNDERVOLTAGE > 0,
-            overvoltage: other & FLT_OVERVOLTAGE > 0,
-            program_or_mem: other & FLT_PROGRAM_OR_MEM > 0,
-            hardware: other & FLT_HARDWARE > 0,
-            overvelocity: other & FLT_OVERVELOCITY > 0,
-            init: other & FLT_INIT > 0,
-            motion: other & FLT_MOTION > 0,
-            range: other & FLT_RANGE > 0,
-            pstage_forced_off: other & FLT_PSTAGE_FORCED_OFF > 0,
-            host_comm_error: other & FLT_HOST_COMM_ERROR > 0,
-            config: other & FLT_CONFIG > 0,
-        }
+        Self::from_bits_retain(other)
+    }
+}
+
+impl fmt::Display for Faults {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_flags(
+            f,
+            self.iter_names().map(|(name, _)| name),
+            self.bits() & !Self::all().bits(),
+        )
+    }
+}
+
+/// List flag names, or `none` if there aren't any.
+pub(crate) fn write_flags<'a>(
+    f: &mut fmt::Formatter<'_>,
+    names: impl Iterator<Item = &'a str>,
+    unknown: u32,
+) -> fmt::Result {
+    let mut names = names.map(str::to_string).collect::<Vec<_>>();
+
+    if unknown != 0 {
+        names.push(format!("{:#x}", unknown));
+    }
+
+    if names.is_empty() {
+        f.write_str("none")
+    } else {
+        f.write_str(&names.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let raw = FLT_OVERCURRENT | FLT_PROGRAM_OR_MEM | 1 << 30;
+        let faults = Faults::from(raw);
+
+        assert!(faults.overcurrent());
+        assert!(faults.contains(Faults::QUEUE_FULL));
+        assert_eq!(faults.to_bits(), raw);
+        assert_eq!(
+            faults.iter().collect::<Vec<_>>(),
+            [
+                Faults::OVERCURRENT,
+                Faults::PROGRAM_OR_MEM,
+                Faults::from_bits_retain(1 << 30)
+            ]
+        );
+        assert_eq!(
+            faults.to_string(),
+            "OVERCURRENT, PROGRAM_OR_MEM, 0x40000000"
+        );
+        assert_eq!(Faults::empty().to_string(), "none");
+
+        let diagnoses = faults.diagnose();
+
+        assert_eq!(diagnoses.len(), 3);
+        assert_eq!(diagnoses[0].fault, Faults::OVERCURRENT);
+        assert_eq!(
+            diagnoses[2].explanation,
+            "Fault bits not known to this library."
+        );
     }
 }
@@ -1,154 +1,567 @@
+/// Generate a `bool` accessor for each named flag of a bitflags type.
+macro_rules! flag_accessors {
+    ($($name:ident => $flag:ident),* $(,)?) => {
+        $(
+            #[doc = concat!("`", stringify!($flag), "` is set.")]
+            pub fn $name(&self) -> bool {
+                self.contains(Self::$flag)
+            }
+        )*
+    };
+}
+
+mod async_argon;
+mod backend;
+mod batch;
+#[cfg(not(feature = "pure-rust"))]
+mod buffered;
+mod bus;
+mod config;
+mod defs;
+mod diagnosis;
+mod fast_update;
 mod faults;
+mod monitor;
 mod parameters;
+#[cfg(feature = "pure-rust")]
+mod protocol;
+mod reconnect;
+pub mod registry;
+mod scaling;
+mod shutdown;
+#[cfg(feature = "sim")]
+pub mod sim;
 mod status;
 mod statuscode;
-
+mod torque;
+mod transmission;
+mod transport;
+mod watchdog;
+
+pub use async_argon::AsyncArgon;
+pub use batch::{Batch, BatchResults, Entry};
+#[cfg(not(feature = "pure-rust"))]
+pub use buffered::{BufferedMotion, Stream};
+pub use bus::{Bus, DeviceInfo};
+pub use config::{ConfigChange, DriveConfig};
+use defs::{
+    SMP_CB1_ENABLE, SMP_CB1_QUICKSTOP_RELEASE, SMP_CB1_QUICKSTOP_SET, SMP_MAX_VALUE_MASK,
+    SMP_MIN_VALUE_MASK, SMP_SYSTEM_CONTROL_SAVECFG,
+};
+pub use diagnosis::Diagnosis;
+pub use fast_update::{FastFeedback, FastUpdateFormat};
 pub use faults::Faults;
+pub use monitor::{FaultEvent, FaultKind, FaultMonitor, Transition};
 pub use parameters::ControlMode;
 use parameters::Parameter;
-use simplemotion_sys::{
-    getCumulativeStatus, resetCumulativeStatus, smCloseBus, smOpenBus, smRead1Parameter, smSetParameter, smSetTimeout,
-};
+pub use reconnect::ReconnectPolicy;
+use reconnect::ReconnectState;
+pub use scaling::Scaling;
+pub use shutdown::ShutdownPolicy;
+#[cfg(feature = "sim")]
+use sim::Sim;
 pub use status::Status;
 pub use statuscode::StatusCode;
+use std::convert::TryInto;
 use std::num::TryFromIntError;
-use std::{convert::TryInto, ffi::CString};
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Mutex, MutexGuard,
+};
+use std::time::{Duration, Instant};
+pub use torque::Torque;
+pub use transmission::Transmission;
+pub use transport::Transport;
+pub use watchdog::Watchdog;
+
+/// How often the drive status is read while waiting for it to change.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long the drive has to confirm a change made through the control bits.
+const CONTROL_TIMEOUT: Duration = Duration::from_millis(500);
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error(
-        "Bus open failed. Check the device name and that it is not locked by another program. Code: {0:?}"
+        "Bus open failed. Check the device name and that it is not locked by another program. Code: {0}"
     )]
     OpenFailed(StatusCode),
 
-    #[error("Could not set drive parameter {0:?} to {1}. Code: {2:?}")]
+    #[error("Could not set drive parameter {0:?} to {1}. Code: {2}")]
     SetParameter(Parameter, i32, StatusCode),
 
-    #[error("Could not read drive parameter {0:?}. Code: {1:?}")]
+    #[error("Could not read drive parameter {0:?}. Code: {1}")]
     ReadParameter(Parameter, StatusCode),
 
-    #[error("Failed to read drive status. Code: {0:?}")]
+    #[error("Could not set drive parameter at address {0} to {1}. Code: {2}")]
+    SetRaw(u16, i32, StatusCode),
+
+    #[error("Could not read drive parameter at address {0}. Code: {1}")]
+    ReadRaw(u16, StatusCode),
+
+    #[error("Value {1} for drive parameter {0} is outside the valid range {2}..={3}")]
+    OutOfRange(&'static str, i32, i32, i32),
+
+    #[error("Value for drive parameter {0} can't be represented by the drive")]
+    Unrepresentable(&'static str),
+
+    #[error("Drive parameter at address {0} has invalid value {1}")]
+    InvalidValue(u16, i32),
+
+    #[error("Unknown drive setting {0}")]
+    UnknownParameter(String),
+
+    #[error("Could not access drive configuration file: {0}")]
+    ConfigFile(std::io::Error),
+
+    #[cfg(feature = "serde")]
+    #[error("Invalid drive configuration file: {0}")]
+    ConfigFormat(serde_json::Error),
+
+    #[error("Fast update cycle failed. Code: {0}")]
+    FastUpdate(StatusCode),
+
+    #[error("Buffered motion failed. Code: {0}")]
+    Buffered(StatusCode),
+
+    #[error("Timed out")]
+    Timeout,
+
+    #[error("Drive status did not confirm {0}")]
+    NotConfirmed(&'static str),
+
+    #[error("Drive I/O thread stopped")]
+    Disconnected,
+
+    #[error("No motor torque constant set. Call `Argon::set_torque_constant` first")]
+    NoTorqueConstant,
+
+    #[error("Invalid scaling: {0} must be positive, got {1}")]
+    InvalidScaling(&'static str, i32),
+
+    #[error("Setpoint for {0} counts can't be represented by the drive")]
+    SetpointOutOfRange(f64),
+
+    #[error("Gear ratio must be finite and positive, got {0}")]
+    InvalidGearRatio(f64),
+
+    #[error("No screw pitch set. Use `Transmission::with_screw_pitch` first")]
+    NoScrewPitch,
+
+    #[error("Not supported: {0}")]
+    Unsupported(&'static str),
+
+    #[error("Batch transaction failed. Code: {0}")]
+    Batch(StatusCode),
+
+    #[error("Failed to read drive status. Code: {0}")]
     GetStatus(StatusCode),
 
-    #[error("Failed to reset drive status. Code: {0:?}")]
+    #[error("Failed to reset drive status. Code: {0}")]
     ResetStatus(StatusCode),
 
     #[error("Value conversion failed: {0:?}")]
     ValueConversion(TryFromIntError),
 }
 
+/// Lock a mutex, ignoring poisoning. None of the guarded values can be left inconsistent.
+fn lock<T: ?Sized>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Drive state restored after an automatic reconnect.
+#[derive(Debug, Copy, Clone, Default)]
+struct Restore {
+    control_mode: Option<ControlMode>,
+    setpoint: Option<i32>,
+}
+
+/// An Argon drive at one address on a [`Bus`].
 #[derive(Debug)]
 pub struct Argon {
     address: u8,
-    bus_handle: i64,
-    pid_freq: f64,
+    bus: Bus,
 
-    /// Encoder counts per revolution.
+    /// Unit conversions, read from the drive configuration.
     ///
-    /// This value is multiplied by 4 to account for quadrature encoders.
-    encoder_counts: f64,
+    /// Behind a mutex so it can be read again after the bus is reopened.
+    scaling: Mutex<Scaling>,
+
+    /// The scaling was replaced with [`Argon::set_scaling`], so isn't read from the drive again.
+    custom_scaling: bool,
+
+    /// [`Bus::generation`] when the scaling was last read.
+    generation: AtomicU64,
+
+    /// Format used by [`Argon::fast_update`].
+    fast_update_format: FastUpdateFormat,
+
+    /// Motor torque constant in Nm/A, set by [`Argon::set_torque_constant`].
+    torque_constant: Option<f64>,
+
+    /// Applied on drop and on communication errors.
+    shutdown_policy: ShutdownPolicy,
 
-    /// Read from `[CVL]`
-    velocity_limit: f64,
+    /// The shutdown policy has been applied because of a communication error since the last
+    /// reconnect.
+    comms_shutdown: AtomicBool,
 
-    /// Read from `[MUL]`
-    input_mul: f64,
+    /// Set by [`Argon::set_auto_reconnect`].
+    reconnect_policy: Option<ReconnectPolicy>,
 
-    /// Read from `[DIV]`
-    input_div: f64,
+    /// Progress of automatic reconnection.
+    reconnect_state: Mutex<ReconnectState>,
 
-    /// Device path, e.g. `/dev/ttyUSB0`.
-    device: String,
+    /// Last control mode and setpoint set through this `Argon`.
+    restore: Mutex<Restore>,
 }
 
 impl Argon {
     /// Attempt to connect to an Argon drive at the given device and address.
+    ///
+    /// Use [`Bus::open`] instead to connect to more than one drive on the same device.
     pub fn connect(device: &str, address: u8) -> Result<Self, Error> {
-        log::debug!("Open {}", device);
-
-        // Must be before bus open, must be between 1 and 5000ms
-        unsafe { smSetTimeout(100) };
-
-        let bus_handle = {
-            let device = CString::new(device)
-                .expect("Device name could not be converted to a valid C string");
-
-            let handle: i64 = unsafe { smOpenBus(device.as_ptr()) };
+        Bus::open(device)?.drive(address)
+    }
 
-            if handle >= 0 {
-                Ok(handle)
-            } else {
-                Err(Error::OpenFailed(handle.into()))
-            }
-        }?;
+    /// Attempt to connect to an Argon drive at the given address over a custom transport, e.g. a
+    /// TCP socket or user provided serial port wrapper.
+    pub fn with_transport<T>(transport: T, address: u8) -> Result<Self, Error>
+    where
+        T: Transport + 'static,
+    {
+        Bus::with_transport(transport)?.drive(address)
+    }
 
-        log::debug!("Bus {}", bus_handle);
+    /// Connect to a simulated drive at the given address.
+    #[cfg(feature = "sim")]
+    pub fn with_sim(sim: &Sim, address: u8) -> Result<Self, Error> {
+        Bus::with_sim(sim).drive(address)
+    }
 
-        let bus_status = {
-            let result = unsafe { getCumulativeStatus(bus_handle) };
+    /// Read drive configuration over an open bus.
+    pub(crate) fn init(bus: Bus, address: u8) -> Result<Self, Error> {
+        log::debug!("Bus {:?}", bus);
 
-            Ok(result)
-        }?;
+        let bus_status = bus.handle().cumulative_status();
 
-        log::debug!("Bus status {}", bus_status);
+        log::debug!("Bus status: {}", StatusCode::from(bus_status));
 
         let mut _self = Self {
             address,
-            bus_handle,
-            pid_freq: 0.0,
-            encoder_counts: 0.0,
-            velocity_limit: 0.0,
-            input_mul: 0.0,
-            input_div: 0.0,
-            device: device.to_string(),
+            generation: AtomicU64::new(bus.generation()),
+            bus,
+            scaling: Mutex::new(Scaling::unread()),
+            custom_scaling: false,
+            fast_update_format: FastUpdateFormat::default(),
+            torque_constant: None,
+            shutdown_policy: ShutdownPolicy::default(),
+            comms_shutdown: AtomicBool::new(false),
+            reconnect_policy: None,
+            reconnect_state: Mutex::new(ReconnectState::Connected),
+            restore: Mutex::new(Restore::default()),
         };
 
         // _self.set_parameter(Parameter::BusSpeed, 115200)?;
         // _self.reconnect()?;
 
-        _self.pid_freq = _self.read_parameter(Parameter::PIDFrequency)?.into();
-        _self.encoder_counts = f64::from(_self.read_parameter(Parameter::EncoderPpr)?) * 4.0;
-        _self.velocity_limit = f64::from(_self.read_parameter(Parameter::VelocityLimit)?);
-        _self.input_mul = f64::from(_self.read_parameter(Parameter::InputMul)?);
-        _self.input_div = f64::from(_self.read_parameter(Parameter::InputDiv)?);
+        _self.scaling = Mutex::new(_self.read_config()?);
 
         log::debug!("Initialised: {:#?}", _self);
 
         Ok(_self)
     }
 
-    /// Close and reopen connection to the drive.
+    /// Read the drive configuration used for unit conversions.
+    fn read_config(&self) -> Result<Scaling, Error> {
+        Scaling::new(
+            self.read_direct(Parameter::EncoderPpr)?,
+            self.read_direct(Parameter::PIDFrequency)?,
+            self.read_direct(Parameter::InputMul)?,
+            self.read_direct(Parameter::InputDiv)?,
+            self.read_direct(Parameter::VelocityLimit)?,
+        )
+    }
+
+    /// Read the drive configuration into the scaling again, keeping the transmission. A scaling
+    /// set with [`Argon::set_scaling`] is kept as it is.
+    fn reload_scaling(&self) -> Result<(), Error> {
+        let generation = self.bus.generation();
+
+        if !self.custom_scaling {
+            let config = self.read_config()?;
+            let mut scaling = lock(&self.scaling);
+
+            *scaling = config.with_transmission(*scaling.transmission());
+        }
+
+        self.generation.store(generation, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Whether the bus was reopened since the scaling was last read, possibly through another
+    /// drive on it.
+    fn stale(&self) -> bool {
+        self.generation.load(Ordering::Relaxed) != self.bus.generation()
+    }
+
+    /// Read the drive configuration again if the bus was reopened through another drive.
+    ///
+    /// Errors are logged and the configuration is read again on the next call.
+    fn refresh(&self) {
+        if !self.stale() {
+            return;
+        }
+
+        log::debug!("Bus reopened, reading drive {} configuration", self.address);
+
+        match self.reload_scaling() {
+            Ok(()) => self.comms_shutdown.store(false, Ordering::Relaxed),
+            Err(e) => log::warn!("Failed to read drive {} configuration: {}", self.address, e),
+        }
+    }
+
+    /// Close and reopen connection to the drive, then read the drive configuration again.
+    ///
+    /// When connected with [`Argon::with_transport`], the same transport is reused. The bus is
+    /// shared with any other drives opened from the same [`Bus`], so they are reconnected too and
+    /// read their configuration again before their next transaction.
     pub fn reconnect(&mut self) -> Result<(), Error> {
-        // Close bus. We'll ignore any errors here.
-        let result = unsafe { smCloseBus(self.bus_handle) };
+        self.reopen()?;
 
-        log::debug!("Closing bus, status {}", result);
+        *lock(&self.reconnect_state) = ReconnectState::Connected;
 
+        Ok(())
+    }
+
+    fn reopen(&self) -> Result<(), Error> {
         // unsafe { smSetBaudrate(115200) };
 
-        let bus_handle = {
-            let device = CString::new(self.device.clone())
-                .expect("Device name could not be converted to a valid C string");
+        // Errors when closing the old bus are ignored
+        self.bus.reopen()?;
 
-            let handle: i64 = unsafe { smOpenBus(device.as_ptr()) };
+        self.reload_scaling()?;
 
-            if handle >= 0 {
-                Ok(handle)
-            } else {
-                Err(Error::OpenFailed(handle.into()))
-            }
-        }?;
+        self.comms_shutdown.store(false, Ordering::Relaxed);
 
         log::info!("--> Reconnected");
 
-        self.bus_handle = bus_handle;
+        Ok(())
+    }
+
+    /// Reconnect automatically when a communication error is detected.
+    ///
+    /// Pass `None` to turn automatic reconnection off, which is the default. See
+    /// [`ReconnectPolicy`].
+    ///
+    /// Don't rely on this in a realtime loop. A reconnect attempt runs inside whichever call is
+    /// made when it is due, and can take several bus timeouts.
+    pub fn set_auto_reconnect(&mut self, policy: Option<ReconnectPolicy>) {
+        self.reconnect_policy = policy;
+
+        *lock(&self.reconnect_state) = ReconnectState::Connected;
+    }
+
+    /// Make one reconnect attempt, then restore the last control mode and setpoint.
+    ///
+    /// Returns the state to move to.
+    fn reconnect_attempt(
+        &self,
+        policy: ReconnectPolicy,
+        attempt: u32,
+        error: StatusCode,
+    ) -> ReconnectState {
+        log::warn!(
+            "Reconnecting to drive {}, attempt {}",
+            self.address,
+            attempt
+        );
+
+        // Another drive on the bus may have reopened it already
+        let reopened = if self.stale() {
+            self.reload_scaling()
+        } else {
+            self.reopen()
+        };
+
+        match reopened.and_then(|_| self.restore()) {
+            Ok(()) => ReconnectState::Connected,
+            Err(e) => match policy.delay(attempt) {
+                Some(delay) => {
+                    log::debug!("Reconnect failed: {}. Retrying in {:?}", e, delay);
+
+                    ReconnectState::Reconnecting {
+                        attempt: attempt + 1,
+                        next_attempt: Instant::now() + delay,
+                        error,
+                    }
+                }
+                None => {
+                    log::error!(
+                        "Automatic reconnect failed after {} attempts: {}",
+                        attempt,
+                        e
+                    );
+
+                    ReconnectState::GaveUp
+                }
+            },
+        }
+    }
+
+    /// Make a reconnect attempt if one is due.
+    ///
+    /// Returns the error that started reconnecting while the drive is still disconnected.
+    fn poll_reconnect(&self) -> Result<(), StatusCode> {
+        let policy = match self.reconnect_policy {
+            Some(policy) => policy,
+            None => return Ok(()),
+        };
+
+        let mut state = lock(&self.reconnect_state);
+
+        if let ReconnectState::Reconnecting {
+            attempt,
+            next_attempt,
+            error,
+        } = *state
+        {
+            if Instant::now() < next_attempt {
+                return Err(error);
+            }
+
+            *state = self.reconnect_attempt(policy, attempt, error);
+
+            if *state != ReconnectState::Connected {
+                return Err(error);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn restore(&self) -> Result<(), Error> {
+        let restore = *lock(&self.restore);
+
+        if let Some(mode) = restore.control_mode {
+            self.write_direct(Parameter::ControlMode, mode as i32)?;
+        }
+
+        if let Some(setpoint) = restore.setpoint {
+            self.write_direct(Parameter::AbsoluteSetpoint, setpoint)?;
+        }
 
         Ok(())
     }
 
-    /// Encoder counts.
+    /// Handle a failed transaction. Returns `true` if the connection was recovered and the
+    /// transaction should be retried.
+    fn recover(&self, code: StatusCode) -> bool {
+        // Make the drive safe before trying anything else
+        self.check_comms(code);
+
+        let policy = match self.reconnect_policy {
+            Some(policy) if code.intersects(StatusCode::ErrCommunication | StatusCode::ErrBus) => {
+                policy
+            }
+            _ => return false,
+        };
+
+        let mut state = lock(&self.reconnect_state);
+
+        if *state != ReconnectState::Connected {
+            return false;
+        }
+
+        *state = self.reconnect_attempt(policy, 1, code);
+
+        *state == ReconnectState::Connected
+    }
+
+    /// Run a transaction, reconnecting and retrying once if it fails because of a communication
+    /// error and automatic reconnection is enabled.
+    ///
+    /// While waiting for the next reconnect attempt, fails without touching the bus.
+    pub(crate) fn transact<T>(
+        &self,
+        f: impl Fn(&backend::Handle) -> Result<T, StatusCode>,
+    ) -> Result<T, StatusCode> {
+        self.poll_reconnect()?;
+        self.refresh();
+
+        match f(self.bus.handle()) {
+            Err(code) if self.recover(code) => f(self.bus.handle()),
+            result => result,
+        }
+    }
+
+    /// Write a parameter without any error recovery.
+    fn write_direct(&self, parameter: Parameter, value: i32) -> Result<(), Error> {
+        let result = self
+            .bus
+            .handle()
+            .set_parameter(self.address, parameter as u16, value);
+
+        if result.is_ok() {
+            Ok(())
+        } else {
+            Err(Error::SetParameter(parameter, value, result))
+        }
+    }
+
+    /// Read a parameter without any error recovery.
+    fn read_direct(&self, parameter: Parameter) -> Result<i32, Error> {
+        self.bus
+            .handle()
+            .read_parameter(self.address, parameter as u16)
+            .map_err(|code| Error::ReadParameter(parameter, code))
+    }
+
+    /// The bus this drive is connected through.
+    pub fn bus(&self) -> &Bus {
+        &self.bus
+    }
+
+    /// Drive address on the bus.
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    /// Encoder counts per revolution.
     pub fn encoder_counts(&self) -> f64 {
-        self.encoder_counts
+        self.scaling().counts_per_rev()
+    }
+
+    /// Unit conversions for this drive.
+    ///
+    /// Read from the drive again first if the bus was reopened.
+    pub fn scaling(&self) -> Scaling {
+        self.refresh();
+
+        *lock(&self.scaling)
+    }
+
+    /// Replace the unit conversions for this drive.
+    ///
+    /// The drive configuration is not changed. The scaling is no longer read from the drive, so is
+    /// kept after a reconnect or [`Argon::apply_config`].
+    pub fn set_scaling(&mut self, scaling: Scaling) {
+        *lock(&self.scaling) = scaling;
+
+        self.custom_scaling = true;
+    }
+
+    /// Set the mechanics between the motor and the output, e.g. a belt ratio or ball screw.
+    ///
+    /// All positions and velocities given to or returned from this drive are then those of the
+    /// output.
+    pub fn set_transmission(&mut self, transmission: Transmission) {
+        let scaling = self.scaling().with_transmission(transmission);
+
+        *lock(&self.scaling) = scaling;
     }
 
     /// Set a parameter in the drive.
@@ -158,22 +571,24 @@ impl Argon {
     {
         let value = value.into();
 
-        let result: StatusCode =
-            unsafe { smSetParameter(self.bus_handle, self.address, parameter as i16, value) }
-                .into();
+        let result = self.transact(|handle| {
+            let result = handle.set_parameter(self.address, parameter as u16, value);
 
-        log::trace!(
-            "Set parameter {:?} to {}. Result: {:?}",
-            parameter,
-            value,
-            result
-        );
+            log::trace!(
+                "Set parameter {:?} to {}. Result: {:?}",
+                parameter,
+                value,
+                result
+            );
 
-        if result.is_err() {
-            Err(Error::SetParameter(parameter, value, result))
-        } else {
-            Ok(())
-        }
+            if result.is_ok() {
+                Ok(())
+            } else {
+                Err(result)
+            }
+        });
+
+        result.map_err(|code| Error::SetParameter(parameter, value, code))
     }
 
     /// Read a parameter in the drive.
@@ -183,20 +598,142 @@ impl Argon {
     fn read_parameter(&self, parameter: Parameter) -> Result<i32, Error> {
         // TODO: Check that bus is open
 
-        let mut output = 0;
+        let result = self.transact(|handle| handle.read_parameter(self.address, parameter as u16));
+
+        log::trace!("Read parameter {:?}. Got value {:?}", parameter, result);
+
+        result.map_err(|code| Error::ReadParameter(parameter, code))
+    }
+
+    /// Set a parameter by address.
+    ///
+    /// Useful for parameters not covered by [`registry`] or the methods on this type, e.g. gains,
+    /// torque and following error limits. Use [`Argon::set`] where possible.
+    pub fn write_raw(&self, address: u16, value: i32) -> Result<(), Error> {
+        let result = self.transact(|handle| {
+            let result = handle.set_parameter(self.address, address, value);
+
+            log::trace!(
+                "Set parameter {} to {}. Result: {:?}",
+                address,
+                value,
+                result
+            );
+
+            if result.is_ok() {
+                Ok(())
+            } else {
+                Err(result)
+            }
+        });
+
+        result.map_err(|code| Error::SetRaw(address, value, code))
+    }
+
+    /// Read a parameter by address.
+    ///
+    /// See [`Argon::write_raw`].
+    pub fn read_raw(&self, address: u16) -> Result<i32, Error> {
+        let result = self.transact(|handle| handle.read_parameter(self.address, address));
+
+        log::trace!("Read parameter {}. Got value {:?}", address, result);
 
-        let result: StatusCode = unsafe {
-            smRead1Parameter(self.bus_handle, self.address, parameter as i16, &mut output)
+        result.map_err(|code| Error::ReadRaw(address, code))
+    }
+
+    /// Read the smallest value the parameter at the given address accepts.
+    pub fn min_value(&self, address: u16) -> Result<i32, Error> {
+        self.read_raw(address | SMP_MIN_VALUE_MASK as u16)
+    }
+
+    /// Read the largest value the parameter at the given address accepts.
+    pub fn max_value(&self, address: u16) -> Result<i32, Error> {
+        self.read_raw(address | SMP_MAX_VALUE_MASK as u16)
+    }
+
+    /// Set the data format used by [`Argon::fast_update`].
+    ///
+    /// The drive is assumed to use [`FastUpdateFormat::Default`] until this is called.
+    pub fn set_fast_update_format(&mut self, format: FastUpdateFormat) -> Result<(), Error> {
+        self.set::<registry::FastUpdateCycleFormat>(format as i32)?;
+
+        self.fast_update_format = format;
+
+        Ok(())
+    }
+
+    /// Send a raw setpoint and receive position feedback in a single short packet.
+    ///
+    /// This is much quicker than writing the setpoint and reading feedback parameters separately,
+    /// so is suitable for closing a position loop on the host. The drive is kept enabled.
+    pub fn fast_update(&self, setpoint: i32) -> Result<FastFeedback, Error> {
+        let format = self.fast_update_format;
+        let (min, max) = format.setpoint_range();
+
+        if !(min..=max).contains(&setpoint) {
+            return Err(Error::OutOfRange(
+                "fast update setpoint",
+                setpoint,
+                min,
+                max,
+            ));
         }
-        .into();
 
-        log::trace!("Read parameter {:?}. Got value {:?}", parameter, output);
+        let (out1, out2) = format.encode_command(setpoint, fast_update::CONTROL_ENABLE);
 
-        if result.is_ok() {
-            Ok(output)
-        } else {
-            Err(Error::ReadParameter(parameter, result))
+        let (in1, in2) = self
+            .transact(|handle| handle.fast_update_cycle(self.address, out1, out2))
+            .map_err(Error::FastUpdate)?;
+
+        lock(&self.restore).setpoint = Some(setpoint);
+
+        Ok(format.decode_feedback(in1, in2))
+    }
+
+    /// Start streaming setpoints into the drive's motion buffer.
+    ///
+    /// Setpoints are executed at `sample_rate` Hz. The parameter at the `readback` address, e.g.
+    /// [`registry::ActualPosition`], is returned for every setpoint sent.
+    ///
+    /// Only available with the `sys` backend.
+    #[cfg(not(feature = "pure-rust"))]
+    pub fn buffered_motion(
+        &self,
+        sample_rate: u32,
+        readback: u16,
+    ) -> Result<BufferedMotion<'_>, Error> {
+        BufferedMotion::new(self, sample_rate, readback)
+    }
+
+    /// Start a batch of parameter reads and writes to send in one transaction.
+    pub fn batch(&self) -> Batch<'_> {
+        Batch::new(self)
+    }
+
+    /// Read a typed parameter.
+    pub fn get<P>(&self) -> Result<P::Value, Error>
+    where
+        P: registry::Parameter,
+    {
+        let raw = self.read_raw(P::ADDRESS)?;
+
+        registry::Value::from_raw(raw).ok_or(Error::InvalidValue(P::ADDRESS, raw))
+    }
+
+    /// Set a typed parameter.
+    ///
+    /// Values outside the parameter's valid range are rejected without contacting the drive.
+    pub fn set<P>(&self, value: P::Value) -> Result<(), Error>
+    where
+        P: registry::Writable,
+    {
+        let raw = registry::Value::to_raw(value).ok_or(Error::Unrepresentable(P::NAME))?;
+
+        if !(P::MIN..=P::MAX).contains(&raw) {
+            return Err(Error::OutOfRange(P::NAME, raw, P::MIN, P::MAX));
         }
+
+        self.write_raw(P::ADDRESS, raw)
     }
 
     /// Attempt to clear drive faults.
@@ -206,18 +743,18 @@ impl Argon {
     pub fn clear_faults(&mut self) -> Result<(), Error> {
         self.set_parameter(Parameter::Faults, 0)?;
 
-        let result = unsafe { resetCumulativeStatus(self.bus_handle) };
+        let result = self.bus.handle().reset_cumulative_status();
 
-        if result == StatusCode::Ok as i32 {
+        if result.is_ok() {
             Ok(())
         } else {
-            Err(Error::ResetStatus(result.into()))
+            Err(Error::ResetStatus(result))
         }
     }
 
     /// Get cumulative drive status since last reset.
     pub fn status(&self) -> Result<Status, Error> {
-        // let result = unsafe { getCumulativeStatus(self.bus_handle) };
+        // let result = self.bus.cumulative_status();
         let result = self.read_parameter(Parameter::Status)?;
 
         log::trace!("Raw status result {} {:0b}", result, result);
@@ -232,14 +769,192 @@ impl Argon {
         Ok(Faults::from(param as u32))
     }
 
+    /// Read the drive's identification.
+    pub fn device_info(&self) -> Result<DeviceInfo, Error> {
+        Ok(DeviceInfo {
+            address: self.address,
+            device_type: self.read_parameter(Parameter::DeviceType)? as u32,
+            firmware_version: self.read_parameter(Parameter::FirmwareVersion)? as u32,
+            serial_number: self.read_parameter(Parameter::SerialNumber)? as u32,
+            sm_version: self.read_parameter(Parameter::SmVersion)? as u32,
+        })
+    }
+
+    /// Read a snapshot of the drive's identification and settings.
+    pub fn config(&self) -> Result<DriveConfig, Error> {
+        let parameters = config::SETTINGS
+            .iter()
+            .map(|info| Ok((info.name.to_string(), self.read_raw(info.address)?)))
+            .collect::<Result<_, Error>>()?;
+
+        Ok(DriveConfig {
+            device: self.device_info()?,
+            parameters,
+        })
+    }
+
+    /// Write the settings from a [`DriveConfig`] to the drive, e.g. to set up a replacement drive.
+    ///
+    /// Every value is checked against the registry and the drive's own limits before anything is
+    /// written. Settings missing from `config` are left unchanged, and only settings that differ
+    /// from the drive are written, scaling and motor setup first. Returns the settings that
+    /// differ. With `dry_run`, nothing is written.
+    ///
+    /// The drive is disabled before writing and left disabled. If a write fails, the settings
+    /// already written are restored before the error is returned. If anything changed, the
+    /// configuration is then [saved to flash](Argon::save_config) so it survives a power cycle.
+    pub fn apply_config(
+        &self,
+        config: &DriveConfig,
+        dry_run: bool,
+    ) -> Result<Vec<ConfigChange>, Error> {
+        let settings = config.validate()?;
+
+        let device_type = self.read_parameter(Parameter::DeviceType)? as u32;
+
+        if device_type != config.device.device_type {
+            log::warn!(
+                "Applying configuration from device type {} to device type {}",
+                config.device.device_type,
+                device_type
+            );
+        }
+
+        let mut changes = Vec::new();
+
+        for (parameter, new) in settings {
+            let current = self.read_raw(parameter.address)?;
+
+            if current == new {
+                continue;
+            }
+
+            let min = self.min_value(parameter.address)?;
+            let max = self.max_value(parameter.address)?;
+
+            if !(min..=max).contains(&new) {
+                return Err(Error::OutOfRange(parameter.name, new, min, max));
+            }
+
+            changes.push(ConfigChange {
+                parameter,
+                current,
+                new,
+            });
+        }
+
+        if dry_run || changes.is_empty() {
+            return Ok(changes);
+        }
+
+        if self.status()?.enabled() {
+            log::info!("Disabling drive {} to apply configuration", self.address);
+
+            self.disable()?;
+        }
+
+        for (i, change) in changes.iter().enumerate() {
+            log::info!("Changing {}", change);
+
+            if let Err(e) = self.write_raw(change.parameter.address, change.new) {
+                self.restore_config(&changes[..i]);
+
+                return Err(e);
+            }
+        }
+
+        self.reload_scaling()?;
+        self.save_config()?;
+
+        Ok(changes)
+    }
+
+    /// Write back the previous values of settings changed by [`Argon::apply_config`].
+    fn restore_config(&self, changes: &[ConfigChange]) {
+        for change in changes.iter().rev() {
+            log::info!("Restoring {}", change);
+
+            if let Err(e) = self.write_raw(change.parameter.address, change.current) {
+                log::error!(
+                    "Could not restore {} to {}: {}",
+                    change.parameter.name,
+                    change.current,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Save the drive's current settings to flash, so they are kept after a power cycle.
+    ///
+    /// Settings changed over the bus are otherwise lost when the drive restarts.
+    pub fn save_config(&self) -> Result<(), Error> {
+        log::debug!("Saving drive {} configuration", self.address);
+
+        self.set::<registry::SystemControl>(SMP_SYSTEM_CONTROL_SAVECFG as i32)
+    }
+
+    /// Save the drive's identification and settings to a JSON file.
+    #[cfg(feature = "serde")]
+    pub fn export_config(&self, path: impl AsRef<std::path::Path>) -> Result<DriveConfig, Error> {
+        let config = self.config()?;
+
+        let json = serde_json::to_string_pretty(&config).map_err(Error::ConfigFormat)?;
+
+        std::fs::write(path, json).map_err(Error::ConfigFile)?;
+
+        Ok(config)
+    }
+
+    /// Load a JSON file saved by [`Argon::export_config`] and [apply](Argon::apply_config) it to
+    /// the drive.
+    ///
+    /// Returns the settings that differ. With `dry_run`, nothing is written. Otherwise the
+    /// configuration is saved to flash if anything changed.
+    #[cfg(feature = "serde")]
+    pub fn import_config(
+        &self,
+        path: impl AsRef<std::path::Path>,
+        dry_run: bool,
+    ) -> Result<Vec<ConfigChange>, Error> {
+        let json = std::fs::read_to_string(path).map_err(Error::ConfigFile)?;
+
+        let config = serde_json::from_str(&json).map_err(Error::ConfigFormat)?;
+
+        self.apply_config(&config, dry_run)
+    }
+
+    /// Explain the active drive faults, along with the values of related parameters such as the
+    /// bus voltage for voltage faults.
+    ///
+    /// Related parameters that can't be read are left out.
+    pub fn diagnose_faults(&self) -> Result<Vec<Diagnosis>, Error> {
+        let mut diagnoses = self.faults()?.diagnose();
+
+        for diagnosis in &mut diagnoses {
+            for info in diagnosis.related {
+                match self.read_raw(info.address) {
+                    Ok(value) => diagnosis.readings.push((*info, value)),
+                    Err(e) => log::debug!("Could not read {} for diagnosis: {}", info.name, e),
+                }
+            }
+        }
+
+        Ok(diagnoses)
+    }
+
     /// Read drive fault status.
     pub fn is_online(&self) -> Result<bool, Error> {
-        Ok(self.status()?.run)
+        Ok(self.status()?.run())
     }
 
     /// Set the raw setpoint.
     fn set_absolute_setpoint(&self, setpoint: i32) -> Result<(), Error> {
-        self.set_parameter(Parameter::AbsoluteSetpoint, setpoint)
+        self.set_parameter(Parameter::AbsoluteSetpoint, setpoint)?;
+
+        lock(&self.restore).setpoint = Some(setpoint);
+
+        Ok(())
     }
 
     /// Get current setpoint value.
@@ -249,15 +964,19 @@ impl Argon {
 
     /// Set control mode.
     pub fn set_control_mode(&self, mode: ControlMode) -> Result<(), Error> {
-        self.set_parameter(Parameter::ControlMode, mode as i32)
+        self.set_parameter(Parameter::ControlMode, mode as i32)?;
+
+        lock(&self.restore).control_mode = Some(mode);
+
+        Ok(())
     }
 
     /// Put the drive into position mode and search for the home (index) pulse.
     ///
-    /// A non-zero offset in degrees can be provided to position the shaft at an arbitrary angle
+    /// A non-zero offset in degrees can be provided to position the output at an arbitrary angle
     /// relative to the index. Homing direction is positive.
     pub fn home(&self, offset: f64) -> Result<(), Error> {
-        let offset_counts = (self.encoder_counts() / 360.0) * offset;
+        let offset_counts = self.scaling().degrees_to_counts(offset);
 
         self.set_control_mode(ControlMode::Position)?;
         self.set_parameter(
@@ -267,6 +986,248 @@ impl Argon {
         self.set_parameter(Parameter::HomingControl, 1)
     }
 
+    /// Move to an absolute angle in degrees, relative to the home position.
+    ///
+    /// The drive must be in [`ControlMode::Position`]. Use
+    /// [`Argon::wait_until_target_reached`] to wait for the move to complete.
+    pub fn move_absolute(&self, degrees: f64) -> Result<(), Error> {
+        let counts = self.scaling().degrees_to_counts(degrees);
+
+        self.set_absolute_setpoint(self.scaling().counts_to_setpoint(counts)?)
+    }
+
+    /// Move by an angle in degrees.
+    ///
+    /// The move is relative to the current target rather than the actual position, so repeated
+    /// relative moves don't accumulate following error.
+    pub fn move_relative(&self, degrees: f64) -> Result<(), Error> {
+        let counts = self.scaling().degrees_to_counts(degrees);
+
+        let target = self.absolute_setpoint()?;
+
+        self.set_absolute_setpoint(target.wrapping_add(self.scaling().counts_to_setpoint(counts)?))
+    }
+
+    /// Get the actual position in degrees, relative to the home position.
+    pub fn position_degrees(&self) -> Result<f64, Error> {
+        let counts = f64::from(self.read_parameter(Parameter::ActualPosition)?);
+
+        Ok(self.scaling().counts_to_degrees(counts))
+    }
+
+    /// Wait until the drive reports that it has reached its target, e.g. at the end of a move.
+    pub fn wait_until_target_reached(&self, timeout: Duration) -> Result<(), Error> {
+        if self.poll_status(|status| status.target_reached(), timeout)? {
+            Ok(())
+        } else {
+            Err(Error::Timeout)
+        }
+    }
+
+    /// Read the drive status until it matches a condition. Returns `false` on timeout.
+    fn poll_status(
+        &self,
+        condition: impl Fn(&Status) -> bool,
+        timeout: Duration,
+    ) -> Result<bool, Error> {
+        let start = Instant::now();
+
+        loop {
+            if condition(&self.status()?) {
+                return Ok(true);
+            }
+
+            if start.elapsed() > timeout {
+                return Ok(false);
+            }
+
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Set and clear bits in `SMP_CONTROL_BITS1`, then wait for the drive status to confirm the
+    /// change.
+    fn update_control_bits(
+        &self,
+        set: u32,
+        clear: u32,
+        confirmed: impl Fn(&Status) -> bool,
+        action: &'static str,
+    ) -> Result<(), Error> {
+        let bits = self.read_parameter(Parameter::ControlBits1)? as u32;
+
+        self.set_parameter(Parameter::ControlBits1, ((bits & !clear) | set) as i32)?;
+
+        if self.poll_status(confirmed, CONTROL_TIMEOUT)? {
+            Ok(())
+        } else {
+            Err(Error::NotConfirmed(action))
+        }
+    }
+
+    /// Enable the drive, powering the motor.
+    ///
+    /// Fails with [`Error::NotConfirmed`] if the drive doesn't report being enabled, e.g. because
+    /// of a fault or the hardware enable input being off.
+    pub fn enable(&self) -> Result<(), Error> {
+        self.update_control_bits(SMP_CB1_ENABLE, 0, |status| status.enabled(), "enable")
+    }
+
+    /// Disable the drive. The motor is unpowered and coasts to a stop.
+    pub fn disable(&self) -> Result<(), Error> {
+        self.update_control_bits(0, SMP_CB1_ENABLE, |status| !status.enabled(), "disable")
+    }
+
+    /// Stop the motor as fast as possible and hold it until [`Argon::release_quick_stop`] is
+    /// called.
+    pub fn quick_stop(&self) -> Result<(), Error> {
+        self.update_control_bits(
+            SMP_CB1_QUICKSTOP_SET,
+            SMP_CB1_QUICKSTOP_RELEASE,
+            |status| status.quick_stop_active(),
+            "quick stop",
+        )
+    }
+
+    /// Release a quick stop, allowing the motor to follow the setpoint again.
+    pub fn release_quick_stop(&self) -> Result<(), Error> {
+        self.update_control_bits(
+            SMP_CB1_QUICKSTOP_RELEASE,
+            SMP_CB1_QUICKSTOP_SET,
+            |status| !status.quick_stop_active(),
+            "quick stop release",
+        )
+    }
+
+    /// Set what happens to the motor when this `Argon` is dropped or a communication error is
+    /// detected.
+    ///
+    /// Defaults to [`ShutdownPolicy::LeaveRunning`].
+    pub fn set_shutdown_policy(&mut self, policy: ShutdownPolicy) {
+        self.shutdown_policy = policy;
+    }
+
+    /// The current shutdown policy.
+    pub fn shutdown_policy(&self) -> ShutdownPolicy {
+        self.shutdown_policy
+    }
+
+    /// Apply the [shutdown policy](Argon::set_shutdown_policy) now.
+    ///
+    /// The drive's status is not checked, as the drive may not be responding.
+    pub fn shutdown(&self) -> Result<(), Error> {
+        let write = |parameter, value| self.write_direct(parameter, value);
+        let read = |parameter| self.read_direct(parameter);
+
+        log::debug!("Applying shutdown policy {:?}", self.shutdown_policy);
+
+        match self.shutdown_policy {
+            ShutdownPolicy::ZeroSetpoint => {
+                // The raw position, as converting it through `[MUL]/[DIV]` could round it to a
+                // different point
+                let setpoint = if read(Parameter::ControlMode)? == ControlMode::Position as i32 {
+                    read(Parameter::ActualPosition)?
+                } else {
+                    0
+                };
+
+                write(Parameter::AbsoluteSetpoint, setpoint)
+            }
+            ShutdownPolicy::QuickStop => {
+                let bits = read(Parameter::ControlBits1)? as u32;
+
+                write(
+                    Parameter::ControlBits1,
+                    ((bits & !SMP_CB1_QUICKSTOP_RELEASE) | SMP_CB1_QUICKSTOP_SET) as i32,
+                )
+            }
+            ShutdownPolicy::Disable => {
+                let bits = read(Parameter::ControlBits1)? as u32;
+
+                write(Parameter::ControlBits1, (bits & !SMP_CB1_ENABLE) as i32)
+            }
+            ShutdownPolicy::LeaveRunning => Ok(()),
+        }
+    }
+
+    /// Apply the shutdown policy if a failed transaction looks like the drive or bus went away.
+    ///
+    /// Only done once until the next [`Argon::reconnect`].
+    fn check_comms(&self, code: StatusCode) {
+        if !code.is_comms_error() || self.comms_shutdown.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        log::error!("Communication error: {}, applying shutdown policy", code);
+
+        if let Err(e) = self.shutdown() {
+            log::error!("Failed to apply shutdown policy: {}", e);
+        }
+    }
+
+    /// Enable the drive's host communication watchdog and start a keepalive thread.
+    ///
+    /// See [`Watchdog`].
+    pub fn watchdog(&self, timeout: Duration) -> Result<Watchdog, Error> {
+        Watchdog::start(self.bus.clone(), self.address, timeout)
+    }
+
+    /// Start polling the drive for faults every `interval` in the background.
+    ///
+    /// The last `history` fault events are kept. See [`FaultMonitor`].
+    pub fn fault_monitor(&self, interval: Duration, history: usize) -> FaultMonitor {
+        FaultMonitor::start(self.bus.clone(), self.address, interval, history)
+    }
+
+    /// Set the motor torque constant in Nm/A, used to convert [`Torque::NewtonMetres`] values.
+    ///
+    /// This is a property of the motor, usually found in its datasheet as `Kt`.
+    pub fn set_torque_constant(&mut self, newton_metres_per_amp: f64) {
+        self.torque_constant = Some(newton_metres_per_amp);
+    }
+
+    /// Set the torque setpoint.
+    ///
+    /// The drive must be in [`ControlMode::Torque`]. The drive limits the motor current to the
+    /// [peak limit](Argon::set_peak_torque_limit).
+    ///
+    /// Torque setpoints are sent in mA and aren't scaled by `[MUL]/[DIV]`.
+    pub fn set_torque_setpoint(&self, torque: Torque) -> Result<(), Error> {
+        let milliamps = torque.to_milliamps(self.torque_constant)?;
+
+        self.set_absolute_setpoint(milliamps.round() as i32)
+    }
+
+    /// Get the actual motor current in amps.
+    pub fn actual_current(&self) -> Result<f64, Error> {
+        let milliamps = f64::from(self.read_parameter(Parameter::ActualTorque)?);
+
+        Ok(milliamps / 1000.0)
+    }
+
+    /// Get the actual motor torque in Nm.
+    ///
+    /// Requires the [torque constant](Argon::set_torque_constant) to be set.
+    pub fn actual_torque(&self) -> Result<f64, Error> {
+        let constant = self.torque_constant.ok_or(Error::NoTorqueConstant)?;
+
+        Ok(self.actual_current()? * constant)
+    }
+
+    /// Set the motor current the drive can supply continuously.
+    pub fn set_continuous_torque_limit(&self, torque: Torque) -> Result<(), Error> {
+        let milliamps = torque.to_milliamps(self.torque_constant)?;
+
+        self.set_parameter(Parameter::ContinuousCurrentLimit, milliamps.round() as i32)
+    }
+
+    /// Set the motor current the drive can supply for short periods, e.g. while accelerating.
+    pub fn set_peak_torque_limit(&self, torque: Torque) -> Result<(), Error> {
+        let milliamps = torque.to_milliamps(self.torque_constant)?;
+
+        self.set_parameter(Parameter::PeakCurrentLimit, milliamps.round() as i32)
+    }
+
     pub fn set_homing_complete(&self) -> Result<(), Error> {
         // TODO: Can I move this into `home()` and call it before all the other methods?
         self.set_parameter(Parameter::HomingControl, 0)
@@ -287,50 +1248,465 @@ impl Argon {
     }
 
     /// Get velocity (RPS) setpoint.
+    ///
+    /// **Changed:** the setpoint is scaled by `[MUL]/[DIV]`, matching
+    /// [`set_velocity_rps`](Argon::set_velocity_rps). Previous versions divided by `[DIV]` only,
+    /// so the result differs when `[MUL]` or `[DIV]` is not 1.
     pub fn setpoint_rps(&self) -> Result<f64, Error> {
-        let feedback: f64 = self.absolute_setpoint()?.into();
+        let counts_per_cycle = self.scaling().setpoint_to_counts(self.absolute_setpoint()?);
 
-        // FIXME: input_div just _happens_ to work - find out why/where/if it's correct
-        let rps = (feedback * self.pid_freq) / self.encoder_counts() / self.input_div;
+        let rps = self.scaling().counts_per_cycle_to_rps(counts_per_cycle);
 
         log::trace!("Feedback RPS {}", rps);
 
         Ok(rps)
     }
 
-    /// Scale RPS value to drive setpoint.
-    fn rps_to_setpoint(&self, rps: f64) -> f64 {
-        let max_velocity =
-            (self.velocity_limit * self.pid_freq) / (self.encoder_counts() * self.input_div);
-
-        (rps / max_velocity) * self.velocity_limit * (self.input_mul / self.input_div)
-    }
-
     /// Set the velocity by RPS value.
+    ///
+    /// **Changed:** the setpoint is now scaled by `[DIV]/[MUL]` like every other position and
+    /// velocity setpoint, so the motor turns at `rps`. Previous versions multiplied by `[MUL]`
+    /// only, so the value sent to the drive differs when `[MUL]` or `[DIV]` is not 1. E.g. with
+    /// 2000 PPR, 2500Hz and `[MUL]/[DIV]` = 4/5, 10 RPS was sent as 128 and is now sent as 40.
     pub fn set_velocity_rps(&self, rps: f64) -> Result<(), Error> {
-        let setpoint = self.rps_to_setpoint(rps);
+        let counts_per_cycle = self.scaling().rps_to_counts_per_cycle(rps);
 
-        self.set_absolute_setpoint(setpoint.round() as i32)
+        self.set_absolute_setpoint(self.scaling().counts_to_setpoint(counts_per_cycle)?)
     }
 
     /// Get the actual RPS (Revolutions Per Second).
     pub fn velocity_rps(&mut self) -> Result<f64, Error> {
         let feedback: f64 = self.velocity_raw()?.into();
 
-        let rps = feedback / (self.encoder_counts() / self.pid_freq);
-
-        Ok(rps)
+        Ok(self.scaling().counts_per_cycle_to_rps(feedback))
     }
 }
 
 impl Drop for Argon {
     fn drop(&mut self) {
-        log::debug!("Close Argon connection");
+        if std::thread::panicking() {
+            log::error!(
+                "Panicking, applying shutdown policy to drive {}",
+                self.address
+            );
+        }
+
+        if let Err(e) = self.shutdown() {
+            log::error!("Failed to apply shutdown policy: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        defs::{
+            CM_POSITION, SMP_ABSOLUTE_SETPOINT, SMP_CONTROL_MODE, SMP_ENCODER_PPR,
+            SMP_INPUT_DIVIDER, SMP_INPUT_MULTIPLIER,
+        },
+        sim::{setup, Sim, SimDrive},
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn missing_drive() {
+        let sim = Sim::new();
+
+        assert!(matches!(
+            Argon::with_sim(&sim, 1),
+            Err(Error::ReadParameter(_, StatusCode::ErrNodevice))
+        ));
+    }
+
+    #[test]
+    fn faults() {
+        let (sim, mut argon) = setup();
+
+        assert!(!argon.faults().unwrap().any());
+
+        sim.with_drive(1, |drive| drive.raise_faults(Faults::OVERVOLTAGE));
 
-        let result: StatusCode = unsafe { smCloseBus(self.bus_handle) }.into();
+        let faults = argon.faults().unwrap();
 
-        if result.is_err() {
-            log::error!("Failed to close bus handle: {:?}", result);
+        assert!(faults.overvoltage());
+        assert!(argon.status().unwrap().faultstop());
+
+        argon.clear_faults().unwrap();
+
+        assert!(!argon.faults().unwrap().any());
+    }
+
+    #[test]
+    fn diagnose_faults() {
+        let (sim, argon) = setup();
+
+        assert!(argon.diagnose_faults().unwrap().is_empty());
+
+        sim.with_drive(1, |drive| {
+            drive.raise_faults(Faults::OVERVOLTAGE | Faults::FOLLOWERROR)
+        });
+
+        let diagnoses = argon.diagnose_faults().unwrap();
+
+        assert_eq!(diagnoses.len(), 2);
+        assert_eq!(diagnoses[0].fault, Faults::FOLLOWERROR);
+        assert_eq!(diagnoses[0].readings[0].1, 1000);
+        assert_eq!(diagnoses[1].fault, Faults::OVERVOLTAGE);
+        assert!(diagnoses[1].remedy.contains("braking resistor"));
+        assert!(diagnoses[1]
+            .to_string()
+            .ends_with("[SMP_ACTUAL_BUS_VOLTAGE = 48 (DeviceScaled)]"));
+    }
+
+    #[test]
+    fn raw_attributes() {
+        let sim = Sim::new();
+        sim.add_drive(
+            1,
+            SimDrive::new().with_range(SMP_ENCODER_PPR as u16, 1, 65535),
+        );
+
+        let argon = Argon::with_sim(&sim, 1).unwrap();
+        let ppr = SMP_ENCODER_PPR as u16;
+
+        assert_eq!(argon.min_value(ppr).unwrap(), 1);
+        assert_eq!(argon.max_value(ppr).unwrap(), 65535);
+
+        argon.write_raw(ppr, 4096).unwrap();
+        assert_eq!(argon.read_raw(ppr).unwrap(), 4096);
+
+        assert!(matches!(
+            argon.write_raw(ppr, 0),
+            Err(Error::SetRaw(_, 0, StatusCode::ErrParameter))
+        ));
+        assert!(matches!(
+            argon.min_value(1234),
+            Err(Error::ReadRaw(_, StatusCode::ErrParameter))
+        ));
+    }
+
+    #[test]
+    fn position_moves() {
+        let (sim, argon) = setup();
+        sim.set_transaction_time(Duration::from_millis(10));
+
+        argon.set_control_mode(ControlMode::Position).unwrap();
+
+        argon.move_absolute(90.0).unwrap();
+        argon
+            .wait_until_target_reached(Duration::from_secs(5))
+            .unwrap();
+
+        assert!((argon.position_degrees().unwrap() - 90.0).abs() < 0.5);
+
+        argon.move_relative(-180.0).unwrap();
+        argon
+            .wait_until_target_reached(Duration::from_secs(5))
+            .unwrap();
+
+        assert!((argon.position_degrees().unwrap() + 90.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn enable_and_quick_stop() {
+        let (sim, mut argon) = setup();
+
+        argon.set_velocity_rps(10.0).unwrap();
+        sim.advance(Duration::from_millis(500));
+
+        argon.quick_stop().unwrap();
+        sim.advance(Duration::from_millis(500));
+
+        let status = argon.status().unwrap();
+
+        assert!(status.quick_stop_active());
+        assert!(status.standing_still());
+
+        argon.release_quick_stop().unwrap();
+        sim.advance(Duration::from_millis(500));
+
+        assert!((argon.velocity_rps().unwrap() - 10.0).abs() < 0.5);
+
+        argon.disable().unwrap();
+        assert!(!argon.status().unwrap().enabled());
+
+        argon.enable().unwrap();
+        assert!(argon.status().unwrap().enabled());
+
+        sim.with_drive(1, |drive| drive.raise_faults(Faults::OVERVOLTAGE));
+
+        assert!(matches!(argon.enable(), Err(Error::NotConfirmed("enable"))));
+    }
+
+    #[test]
+    fn shutdown_policy() {
+        let (sim, mut argon) = setup();
+
+        assert_eq!(argon.shutdown_policy(), ShutdownPolicy::LeaveRunning);
+
+        argon.set_shutdown_policy(ShutdownPolicy::ZeroSetpoint);
+        argon.set_velocity_rps(10.0).unwrap();
+        sim.advance(Duration::from_millis(500));
+
+        // A lost reply stops the motor
+        sim.fail_transactions(1);
+
+        assert!(matches!(
+            argon.status(),
+            Err(Error::ReadParameter(_, StatusCode::ErrCommunication))
+        ));
+
+        sim.advance(Duration::from_millis(500));
+
+        assert!(argon.status().unwrap().standing_still());
+
+        // Only once until reconnected
+        argon.set_velocity_rps(10.0).unwrap();
+        sim.fail_transactions(1);
+        argon.status().unwrap_err();
+        sim.advance(Duration::from_millis(500));
+
+        assert!(!argon.status().unwrap().standing_still());
+
+        argon.set_shutdown_policy(ShutdownPolicy::QuickStop);
+        drop(argon);
+
+        let argon = Argon::with_sim(&sim, 1).unwrap();
+
+        assert!(argon.status().unwrap().quick_stop_active());
+    }
+
+    /// Position is held at the raw feedback position, not one scaled by `[MUL]/[DIV]`.
+    #[test]
+    fn shutdown_holds_position() {
+        let sim = Sim::new();
+        sim.add_drive(
+            1,
+            SimDrive::new()
+                .with_parameter(SMP_INPUT_MULTIPLIER as u16, 3)
+                .with_parameter(SMP_INPUT_DIVIDER as u16, 7),
+        );
+        sim.set_transaction_time(Duration::from_millis(10));
+
+        let mut argon = Argon::with_sim(&sim, 1).unwrap();
+
+        argon.set_control_mode(ControlMode::Position).unwrap();
+        argon.write_raw(SMP_ABSOLUTE_SETPOINT as u16, 1234).unwrap();
+        argon
+            .wait_until_target_reached(Duration::from_secs(5))
+            .unwrap();
+
+        argon.set_shutdown_policy(ShutdownPolicy::ZeroSetpoint);
+        argon.shutdown().unwrap();
+
+        let (setpoint, position) = sim
+            .with_drive(1, |drive| {
+                (drive.parameter(SMP_ABSOLUTE_SETPOINT), drive.position())
+            })
+            .unwrap();
+
+        // Scaling the feedback position by `[MUL]/[DIV]` would put the setpoint near 2880
+        assert_eq!(f64::from(setpoint), position.round());
+        assert!((position - 1234.0).abs() < 10.0);
+    }
+
+    #[test]
+    fn auto_reconnect() {
+        let (sim, mut argon) = setup();
+
+        argon.set_auto_reconnect(Some(
+            ReconnectPolicy::new().with_initial_delay(Duration::ZERO),
+        ));
+        argon.set_control_mode(ControlMode::Position).unwrap();
+        argon.move_absolute(90.0).unwrap();
+
+        // Drive power cycled with a different encoder, and a few replies lost
+        sim.add_drive(
+            1,
+            SimDrive::new().with_parameter(SMP_ENCODER_PPR as u16, 1000),
+        );
+        sim.fail_transactions(3);
+
+        // One attempt per call
+        let mut calls = 1;
+
+        while let Err(e) = argon.status() {
+            assert!(matches!(
+                e,
+                Error::ReadParameter(_, StatusCode::ErrCommunication)
+            ));
+
+            calls += 1;
         }
+
+        assert_eq!(calls, 3);
+        assert_eq!(argon.encoder_counts(), 4000.0);
+
+        let restored = sim
+            .with_drive(1, |drive| {
+                (
+                    drive.parameter(SMP_CONTROL_MODE),
+                    drive.parameter(SMP_ABSOLUTE_SETPOINT),
+                )
+            })
+            .unwrap();
+
+        assert_eq!(restored, (CM_POSITION as i32, 2000));
+    }
+
+    #[test]
+    fn reconnect_gives_up() {
+        let (sim, mut argon) = setup();
+
+        argon.set_auto_reconnect(Some(
+            ReconnectPolicy::new()
+                .with_initial_delay(Duration::ZERO)
+                .with_max_attempts(2),
+        ));
+
+        // The failed call and both attempts
+        sim.fail_transactions(3);
+
+        assert!(argon.status().is_err());
+        assert!(argon.status().is_err());
+        assert!(argon.status().is_ok());
+
+        // No more attempts after giving up
+        sim.fail_transactions(1);
+
+        assert!(matches!(
+            argon.status(),
+            Err(Error::ReadParameter(_, StatusCode::ErrCommunication))
+        ));
+
+        argon.reconnect().unwrap();
+        sim.fail_transactions(1);
+
+        assert!(argon.status().is_ok());
+    }
+
+    /// Calls between attempts fail without waiting or touching the bus.
+    #[test]
+    fn reconnect_backoff() {
+        let (sim, mut argon) = setup();
+
+        argon.set_auto_reconnect(Some(
+            ReconnectPolicy::new().with_initial_delay(Duration::from_secs(3600)),
+        ));
+
+        // The failed call and the first attempt
+        sim.fail_transactions(2);
+
+        assert!(argon.status().is_err());
+
+        sim.advance(Duration::from_millis(50));
+
+        assert!(matches!(
+            argon.status(),
+            Err(Error::ReadParameter(_, StatusCode::ErrCommunication))
+        ));
+
+        let since_packet = sim.with_drive(1, |drive| drive.since_packet()).unwrap();
+
+        assert!(since_packet >= Duration::from_millis(50));
+
+        argon.reconnect().unwrap();
+
+        assert!(argon.status().is_ok());
+    }
+
+    /// The shutdown policy is applied before reconnecting, and isn't undone by it.
+    #[test]
+    fn reconnect_after_shutdown() {
+        let (sim, mut argon) = setup();
+
+        argon.set_auto_reconnect(Some(ReconnectPolicy::new()));
+        argon.set_shutdown_policy(ShutdownPolicy::Disable);
+
+        assert!(argon.status().unwrap().enabled());
+
+        sim.fail_transactions(1);
+
+        assert!(!argon.status().unwrap().enabled());
+    }
+
+    /// A scaling set by the user isn't replaced by the drive configuration after a reconnect.
+    #[test]
+    fn reconnect_keeps_custom_scaling() {
+        let (_sim, mut argon) = setup();
+
+        argon.set_scaling(Scaling::new(1000, 2500, 1, 1, 1000).unwrap());
+        argon.reconnect().unwrap();
+
+        assert_eq!(argon.encoder_counts(), 4000.0);
+
+        let (_sim, mut argon) = setup();
+
+        argon.set_transmission(Transmission::new().with_inverted(true));
+        argon.reconnect().unwrap();
+
+        assert_eq!(argon.encoder_counts(), 8000.0);
+        assert!(argon.scaling().transmission().inverted());
+    }
+
+    /// Reopening a shared bus makes every drive on it read its configuration again.
+    #[test]
+    fn reopen_shared_bus() {
+        let sim = Sim::new();
+        sim.add_drive(1, SimDrive::new());
+        sim.add_drive(2, SimDrive::new());
+
+        let bus = Bus::with_sim(&sim);
+        let mut spindle = bus.drive(1).unwrap();
+        let axis = bus.drive(2).unwrap();
+
+        assert_eq!(axis.encoder_counts(), 8000.0);
+
+        sim.add_drive(
+            2,
+            SimDrive::new().with_parameter(SMP_ENCODER_PPR as u16, 1000),
+        );
+
+        spindle.reconnect().unwrap();
+
+        assert_eq!(axis.encoder_counts(), 4000.0);
+    }
+
+    /// Same sequence of calls as the spindle state machine in `examples/linuxcnc.rs`: spin up,
+    /// stop, then orient.
+    #[test]
+    fn spindle_orient() {
+        let (sim, mut argon) = setup();
+
+        argon.set_control_mode(ControlMode::Velocity).unwrap();
+        argon.set_velocity_rps(20.0).unwrap();
+        sim.advance(Duration::from_millis(500));
+
+        argon.set_velocity_rps(0.0).unwrap();
+
+        let mut ticks = 0;
+
+        while argon.velocity_rps().unwrap() != 0.0 {
+            sim.advance(Duration::from_millis(10));
+
+            ticks += 1;
+
+            assert!(ticks < 100, "spindle did not stop");
+        }
+
+        argon.home(90.0).unwrap();
+
+        assert!(argon.status().unwrap().homing());
+
+        sim.advance(Duration::from_secs(1));
+
+        assert!(!argon.status().unwrap().homing());
+
+        argon.set_homing_complete().unwrap();
+
+        let position = sim.with_drive(1, |drive| drive.position()).unwrap();
+
+        assert_eq!(position, argon.encoder_counts() / 4.0);
     }
 }
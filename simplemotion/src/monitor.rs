@@ -0,0 +1,376 @@
+//! Fault monitoring.
+
+use crate::{
+    defs::{STAT_FAULTSTOP, STAT_FERROR_WARNING, STAT_PERMANENT_STOP, STAT_STO_ACTIVE},
+    lock,
+    parameters::Parameter,
+    Bus, Faults, StatusCode,
+};
+use std::{
+    collections::VecDeque,
+    sync::{
+        mpsc::{self, RecvTimeoutError, TrySendError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+/// Whether a fault appeared or went away.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Transition {
+    Raised,
+    Cleared,
+}
+
+/// A single fault condition.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FaultKind {
+    /// A drive fault from `SMP_FAULTS`, with exactly one fault bit set. The bit may not be one
+    /// of the named [`Faults`].
+    Drive(Faults),
+
+    /// The drive stopped because of a fault.
+    FaultStop,
+
+    /// The following error is close to its limit.
+    FollowingErrorWarning,
+
+    /// Safe torque off is active.
+    SafeTorqueOff,
+
+    /// The drive stopped and needs a power cycle.
+    PermanentStop,
+
+    /// A single error bit from the bus cumulative status, or from a failed poll.
+    Communication(StatusCode),
+}
+
+/// A fault being raised or cleared, seen by a [`FaultMonitor`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FaultEvent {
+    pub transition: Transition,
+    pub kind: FaultKind,
+
+    /// When the poll that saw the change was made.
+    pub timestamp: SystemTime,
+}
+
+/// Polls a drive for faults in the background and records when they are raised and cleared.
+///
+/// Created by [`Argon::fault_monitor`](crate::Argon::fault_monitor). Each poll reads `SMP_FAULTS`,
+/// `SMP_STATUS` and the bus cumulative status, and compares them to the previous poll. Every
+/// change is sent as a [`FaultEvent`] and added to a bounded history, dropping the oldest event
+/// when it is full.
+///
+/// Drive faults are latched until [`Argon::clear_faults`](crate::Argon::clear_faults), so faults
+/// that come and go between polls are still seen. The cumulative status is shared by all drives
+/// on the bus.
+///
+/// Dropping the `FaultMonitor` stops polling.
+///
+/// ```rust,no_run
+/// use simplemotion::{Argon, Transition};
+/// use std::time::Duration;
+///
+/// let argon = Argon::connect("/dev/ttyUSB0", 1)?;
+///
+/// let monitor = argon.fault_monitor(Duration::from_millis(50), 100);
+///
+/// while let Some(event) = monitor.recv_timeout(Duration::from_secs(10)) {
+///     if event.transition == Transition::Raised {
+///         println!("Fault raised: {:?}", event.kind);
+///     }
+/// }
+///
+/// // Post-mortem
+/// for event in monitor.history() {
+///     println!("{:?}", event);
+/// }
+/// # Ok::<(), simplemotion::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct FaultMonitor {
+    events: mpsc::Receiver<FaultEvent>,
+    history: Arc<Mutex<VecDeque<FaultEvent>>>,
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FaultMonitor {
+    pub(crate) fn start(bus: Bus, address: u8, interval: Duration, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        let history = Arc::new(Mutex::new(VecDeque::with_capacity(capacity)));
+        let (sender, events) = mpsc::sync_channel(capacity);
+        let (stop, stopped) = mpsc::channel();
+
+        let thread = {
+            let history = history.clone();
+
+            thread::Builder::new()
+                .name(format!("simplemotion-faults-{}", address))
+                .spawn(move || {
+                    let mut poller = Poller {
+                        bus,
+                        address,
+                        capacity,
+                        history,
+                        sender,
+                        previous: Snapshot::default(),
+                    };
+
+                    // Poll straight away so faults present at startup are reported
+                    poller.poll();
+
+                    // Stops when the `FaultMonitor` is dropped
+                    while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                        poller.poll();
+                    }
+
+                    log::debug!("Fault monitor for drive {} stopped", address);
+                })
+                .expect("Failed to spawn fault monitor thread")
+        };
+
+        Self {
+            events,
+            history,
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Get the next event if there is one.
+    pub fn try_recv(&self) -> Option<FaultEvent> {
+        self.events.try_recv().ok()
+    }
+
+    /// Wait up to `timeout` for the next event.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<FaultEvent> {
+        self.events.recv_timeout(timeout).ok()
+    }
+
+    /// Recorded events, oldest first.
+    pub fn history(&self) -> Vec<FaultEvent> {
+        lock(&self.history).iter().copied().collect()
+    }
+
+    /// Forget all recorded events.
+    pub fn clear_history(&self) {
+        lock(&self.history).clear();
+    }
+}
+
+impl Drop for FaultMonitor {
+    fn drop(&mut self) {
+        // Disconnecting the channel stops the thread
+        self.stop.take();
+
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("Fault monitor thread panicked");
+            }
+        }
+    }
+}
+
+/// Fault bits seen by one poll.
+#[derive(Debug, Default, Copy, Clone)]
+struct Snapshot {
+    faults: u32,
+    status: u32,
+    comms: u32,
+}
+
+struct Poller {
+    bus: Bus,
+    address: u8,
+    capacity: usize,
+    history: Arc<Mutex<VecDeque<FaultEvent>>>,
+    sender: mpsc::SyncSender<FaultEvent>,
+    previous: Snapshot,
+}
+
+impl Poller {
+    fn poll(&mut self) {
+        let timestamp = SystemTime::now();
+        let mut next = self.previous;
+        let mut comms = StatusCode::empty();
+
+        {
+            let handle = self.bus.handle();
+
+            // Keep the previous bits if a read fails, so nothing is reported as cleared
+            match handle.read_parameter(self.address, Parameter::Faults as u16) {
+                Ok(faults) => next.faults = faults as u32,
+                Err(code) => comms |= code,
+            }
+
+            match handle.read_parameter(self.address, Parameter::Status as u16) {
+                Ok(status) => next.status = status as u32 & STATUS_FAULTS,
+                Err(code) => comms |= code,
+            }
+
+            comms |= StatusCode::from(handle.cumulative_status());
+        }
+
+        next.comms = (comms & StatusCode::ERRORS).bits() as u32;
+
+        let mut events = Vec::new();
+
+        transitions(self.previous.faults, next.faults, &mut events, |bit| {
+            // Bits unknown to this library are reported too
+            Some(FaultKind::Drive(Faults::from_bits_retain(bit)))
+        });
+
+        transitions(
+            self.previous.status,
+            next.status,
+            &mut events,
+            |bit| match bit {
+                STAT_FAULTSTOP => Some(FaultKind::FaultStop),
+                STAT_FERROR_WARNING => Some(FaultKind::FollowingErrorWarning),
+                STAT_STO_ACTIVE => Some(FaultKind::SafeTorqueOff),
+                STAT_PERMANENT_STOP => Some(FaultKind::PermanentStop),
+                _ => None,
+            },
+        );
+
+        transitions(self.previous.comms, next.comms, &mut events, |bit| {
+            Some(FaultKind::Communication(StatusCode::from(bit)))
+        });
+
+        self.previous = next;
+
+        for (transition, kind) in events {
+            self.record(FaultEvent {
+                transition,
+                kind,
+                timestamp,
+            });
+        }
+    }
+
+    fn record(&self, event: FaultEvent) {
+        log::debug!("Drive {} fault event {:?}", self.address, event);
+
+        {
+            let mut history = lock(&self.history);
+
+            if history.len() == self.capacity {
+                history.pop_front();
+            }
+
+            history.push_back(event);
+        }
+
+        // The history keeps the event if nobody is reading them
+        if let Err(TrySendError::Full(_)) = self.sender.try_send(event) {
+            log::warn!(
+                "Fault event queue for drive {} is full, dropping event",
+                self.address
+            );
+        }
+    }
+}
+
+/// `SMP_STATUS` bits reported as faults.
+const STATUS_FAULTS: u32 =
+    STAT_FAULTSTOP | STAT_FERROR_WARNING | STAT_STO_ACTIVE | STAT_PERMANENT_STOP;
+
+/// Find the bits that changed between two polls.
+fn transitions(
+    previous: u32,
+    next: u32,
+    events: &mut Vec<(Transition, FaultKind)>,
+    kind: impl Fn(u32) -> Option<FaultKind>,
+) {
+    for bit in (0..u32::BITS).map(|shift| 1 << shift) {
+        if (previous ^ next) & bit == 0 {
+            continue;
+        }
+
+        let transition = if next & bit != 0 {
+            Transition::Raised
+        } else {
+            Transition::Cleared
+        };
+
+        if let Some(kind) = kind(bit) {
+            events.push((transition, kind));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::setup;
+    use std::time::Instant;
+
+    /// Wait for the monitor to report the given event.
+    ///
+    /// The timeout is only there to fail a broken test rather than hang it, so it's far longer
+    /// than the poll interval.
+    fn expect_event(monitor: &FaultMonitor, transition: Transition, kind: FaultKind) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        while let Some(event) =
+            monitor.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        {
+            if event.transition == transition && event.kind == kind {
+                return;
+            }
+        }
+
+        panic!("No {:?} event for {:?}", transition, kind);
+    }
+
+    #[test]
+    fn fault_monitor() {
+        let (sim, mut argon) = setup();
+
+        let monitor = argon.fault_monitor(Duration::from_millis(5), 8);
+        let overvoltage = FaultKind::Drive(Faults::OVERVOLTAGE);
+
+        sim.with_drive(1, |drive| drive.raise_faults(Faults::OVERVOLTAGE));
+
+        expect_event(&monitor, Transition::Raised, overvoltage);
+        expect_event(&monitor, Transition::Raised, FaultKind::FaultStop);
+
+        argon.clear_faults().unwrap();
+
+        expect_event(&monitor, Transition::Cleared, overvoltage);
+        expect_event(&monitor, Transition::Cleared, FaultKind::FaultStop);
+
+        // Lost replies stay in the cumulative status until the next reset
+        sim.fail_transactions(1);
+
+        expect_event(
+            &monitor,
+            Transition::Raised,
+            FaultKind::Communication(StatusCode::ErrCommunication),
+        );
+
+        let history = monitor.history();
+
+        assert_eq!(history.len(), 5);
+        assert_eq!(history[0].kind, overvoltage);
+        assert!(history
+            .windows(2)
+            .all(|pair| pair[0].timestamp <= pair[1].timestamp));
+    }
+
+    #[test]
+    fn unknown_fault() {
+        let (sim, argon) = setup();
+
+        let monitor = argon.fault_monitor(Duration::from_millis(5), 8);
+        let unknown = Faults::from_bits_retain(1 << 30);
+
+        sim.with_drive(1, |drive| drive.raise_faults(unknown));
+
+        expect_event(&monitor, Transition::Raised, FaultKind::Drive(unknown));
+        assert_eq!(unknown.to_string(), "0x40000000");
+    }
+}
@@ -1,8 +1,10 @@
-use simplemotion_sys::{
-    CM_NONE, CM_POSITION, CM_TORQUE, CM_VELOCITY, SMP_ABSOLUTE_SETPOINT, SMP_ACTUAL_VELOCITY_FB,
-    SMP_BUS_SPEED, SMP_CONTROL_BITS1, SMP_CONTROL_MODE, SMP_ENCODER_PPR, SMP_FAULTS,
-    SMP_HOMING_CONTROL, SMP_INPUT_DIVIDER, SMP_INPUT_MULTIPLIER, SMP_PID_FREQUENCY, SMP_STATUS,
-    SMP_TRAJ_PLANNER_HOMING_OFFSET, SMP_TRAJ_PLANNER_VEL,
+use crate::defs::{
+    CM_NONE, CM_POSITION, CM_TORQUE, CM_VELOCITY, SMP_ABSOLUTE_SETPOINT, SMP_ACTUAL_POSITION_FB,
+    SMP_ACTUAL_TORQUE, SMP_ACTUAL_VELOCITY_FB, SMP_BUS_SPEED, SMP_CONTINUOUS_CURRENT_LIMIT,
+    SMP_CONTROL_BITS1, SMP_CONTROL_MODE, SMP_DEVICE_TYPE, SMP_ENCODER_PPR, SMP_FAULTS,
+    SMP_FIRMWARE_VERSION, SMP_HOMING_CONTROL, SMP_INPUT_DIVIDER, SMP_INPUT_MULTIPLIER,
+    SMP_PEAK_CURRENT_LIMIT, SMP_PID_FREQUENCY, SMP_SERIAL_NR, SMP_SM_VERSION, SMP_STATUS,
+    SMP_TIMEOUT, SMP_TRAJ_PLANNER_HOMING_OFFSET, SMP_TRAJ_PLANNER_VEL,
 };
 use std::fmt;
 
@@ -15,8 +17,16 @@ pub enum Parameter {
     ControlMode = SMP_CONTROL_MODE as isize,
     HomingControl = SMP_HOMING_CONTROL as isize,
     TrajPlannerHomingOffset = SMP_TRAJ_PLANNER_HOMING_OFFSET as isize,
+    /// Position readout.
+    ActualPosition = SMP_ACTUAL_POSITION_FB as isize,
     /// Velocity readout.
     ActualVelocity = SMP_ACTUAL_VELOCITY_FB as isize,
+    /// Motor current readout in mA.
+    ActualTorque = SMP_ACTUAL_TORQUE as isize,
+    /// Continuous motor current limit in mA.
+    ContinuousCurrentLimit = SMP_CONTINUOUS_CURRENT_LIMIT as isize,
+    /// Peak motor current limit in mA.
+    PeakCurrentLimit = SMP_PEAK_CURRENT_LIMIT as isize,
     EncoderPpr = SMP_ENCODER_PPR as isize,
     PIDFrequency = SMP_PID_FREQUENCY as isize,
     ControlBits1 = SMP_CONTROL_BITS1 as isize,
@@ -29,10 +39,18 @@ pub enum Parameter {
     /// TODO: Check if the value is correct.
     VelocityLimit = SMP_TRAJ_PLANNER_VEL as isize,
     BusSpeed = SMP_BUS_SPEED as isize,
+    DeviceType = SMP_DEVICE_TYPE as isize,
+    FirmwareVersion = SMP_FIRMWARE_VERSION as isize,
+    SerialNumber = SMP_SERIAL_NR as isize,
+    /// SimpleMotion protocol version implemented by the device.
+    SmVersion = SMP_SM_VERSION as isize,
+    /// Host communication timeout in ms. `0` disables it.
+    WatchdogTimeout = SMP_TIMEOUT as isize,
 }
 
 /// Control mode.
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ControlMode {
     Torque = CM_TORQUE as isize,
     Velocity = CM_VELOCITY as isize,
@@ -0,0 +1,367 @@
+//! SimpleMotion V2 wire protocol.
+//!
+//! A native implementation of the SM485 packet framing, CRC and SM payload commands found in
+//! `simplemotion.c`/`sm485.h`. Used by the `pure-rust` backend.
+
+use crate::StatusCode;
+use std::io::{self, Read};
+
+/// Bit set in a command ID when the packet is a reply.
+const SMCMD_MASK_RETURN: u8 = 1;
+
+/// Command ID flag for packets with a fixed two word payload.
+const SMCMD_MASK_2_PARAMS: u8 = 2;
+
+/// Command ID flag for packets with a variable length payload.
+const SMCMD_MASK_N_PARAMS: u8 = 4;
+
+/// Execute the SM payload commands in the packet immediately.
+pub const SMCMD_INSTANT_CMD: u8 = (4 << 3) | SMCMD_MASK_N_PARAMS;
+
+/// Reply to [`SMCMD_INSTANT_CMD`].
+pub const SMCMD_INSTANT_CMD_RET: u8 = SMCMD_INSTANT_CMD | SMCMD_MASK_RETURN;
+
+/// Exchange two words of setpoint and feedback with a device.
+pub const SMCMD_FAST_UPDATE_CYCLE: u8 = (2 << 3) | SMCMD_MASK_2_PARAMS;
+
+/// Reply to [`SMCMD_FAST_UPDATE_CYCLE`].
+pub const SMCMD_FAST_UPDATE_CYCLE_RET: u8 = SMCMD_FAST_UPDATE_CYCLE | SMCMD_MASK_RETURN;
+
+/// Maximum number of payload bytes in a single packet.
+pub const MAX_PAYLOAD_BYTES: usize = 120;
+
+/// SM payload command type: set the address of the parameter written by the next value command.
+const SMPCMD_SETPARAMADDR: u8 = 2;
+
+/// SM payload command type: 22 bit signed value.
+const SMPCMD_24B: u8 = 1;
+
+/// SM payload command type: 30 bit signed value.
+const SMPCMD_32B: u8 = 0;
+
+/// SM payload return type: 14 bit command status.
+pub const SMPRET_CMD_STATUS: i32 = 3;
+
+/// SM payload return type: 14 bit signed value.
+pub const SMPRET_16B: i32 = 2;
+
+/// SM payload return type: 22 bit signed value.
+pub const SMPRET_24B: i32 = 1;
+
+/// SM payload return type: 30 bit signed value.
+pub const SMPRET_32B: i32 = 0;
+
+/// CRC-16 with polynomial `0x8005` (reflected) and an initial value of `0xffff`.
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xffff, |crc, byte| {
+        (0..8).fold(crc ^ u16::from(*byte), |crc, _| {
+            if crc & 1 == 1 {
+                (crc >> 1) ^ 0xa001
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+/// A single command in an SM payload.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Command {
+    /// Select the parameter address that subsequent value commands write to.
+    SetParamAddr(u16),
+
+    /// Write a value that fits in 22 bits.
+    Value24(i32),
+
+    /// Write a value that fits in 30 bits.
+    Value32(i32),
+}
+
+impl Command {
+    /// Encoded length in bytes.
+    pub fn len(&self) -> usize {
+        match self {
+            Self::SetParamAddr(_) => 2,
+            Self::Value24(_) => 3,
+            Self::Value32(_) => 4,
+        }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        match *self {
+            Self::SetParamAddr(address) => {
+                let word = (u16::from(SMPCMD_SETPARAMADDR) << 14) | (address & 0x3fff);
+
+                out.extend_from_slice(&word.to_be_bytes());
+            }
+            Self::Value24(value) => {
+                let word = (u32::from(SMPCMD_24B) << 22) | (value as u32 & 0x3f_ffff);
+
+                out.extend_from_slice(&word.to_be_bytes()[1..]);
+            }
+            Self::Value32(value) => {
+                let word = (u32::from(SMPCMD_32B) << 30) | (value as u32 & 0x3fff_ffff);
+
+                out.extend_from_slice(&word.to_be_bytes());
+            }
+        }
+    }
+}
+
+/// A value returned by the device for each executed [`Command`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Return {
+    /// Command status, e.g. `SMP_CMD_STATUS_ACK`.
+    CommandStatus(u16),
+
+    /// Value of the parameter selected by `SMP_RETURN_PARAM_ADDR`.
+    Value(i32),
+}
+
+/// Sign extend the lowest `bits` bits of `value`.
+fn sign_extend(value: u32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+
+    ((value << shift) as i32) >> shift
+}
+
+/// Encode a list of commands into an SM payload.
+pub fn encode_commands(commands: &[Command]) -> Result<Vec<u8>, StatusCode> {
+    let mut payload = Vec::with_capacity(commands.iter().map(Command::len).sum());
+
+    for command in commands {
+        command.encode(&mut payload);
+    }
+
+    if payload.len() > MAX_PAYLOAD_BYTES {
+        Err(StatusCode::ErrLength)
+    } else {
+        Ok(payload)
+    }
+}
+
+/// Decode the return values in a reply payload.
+pub fn decode_returns(mut payload: &[u8]) -> Result<Vec<Return>, StatusCode> {
+    let mut returns = Vec::new();
+
+    while let Some(first) = payload.first() {
+        let kind = i32::from(first >> 6);
+
+        let len = match kind {
+            SMPRET_CMD_STATUS | SMPRET_16B => 2,
+            SMPRET_24B => 3,
+            _ => 4,
+        };
+
+        if payload.len() < len {
+            return Err(StatusCode::ErrLength);
+        }
+
+        let (bytes, rest) = payload.split_at(len);
+
+        let raw = bytes
+            .iter()
+            .fold(0u32, |acc, byte| (acc << 8) | u32::from(*byte));
+
+        let value = match kind {
+            SMPRET_CMD_STATUS => Return::CommandStatus((raw & 0x3fff) as u16),
+            SMPRET_16B => Return::Value(sign_extend(raw, 14)),
+            SMPRET_24B => Return::Value(sign_extend(raw, 22)),
+            _ => Return::Value(sign_extend(raw, 30)),
+        };
+
+        returns.push(value);
+
+        payload = rest;
+    }
+
+    Ok(returns)
+}
+
+/// Frame a payload into an SM485 packet: command, payload length, address, payload and CRC.
+pub fn encode_packet(command: u8, address: u8, payload: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(payload.len() + 5);
+
+    packet.push(command);
+    packet.push(payload.len() as u8);
+    packet.push(address);
+    packet.extend_from_slice(payload);
+
+    let crc = crc16(&packet);
+
+    packet.extend_from_slice(&crc.to_be_bytes());
+
+    packet
+}
+
+/// Frame a fast update cycle packet: command, address, two words and CRC. There is no length
+/// byte as the payload size is fixed.
+pub fn encode_fast_update(address: u8, out1: u16, out2: u16) -> Vec<u8> {
+    let mut packet = vec![SMCMD_FAST_UPDATE_CYCLE, address];
+
+    packet.extend_from_slice(&out1.to_be_bytes());
+    packet.extend_from_slice(&out2.to_be_bytes());
+
+    let crc = crc16(&packet);
+
+    packet.extend_from_slice(&crc.to_be_bytes());
+
+    packet
+}
+
+/// Read and validate a fast update cycle reply, returning the two feedback words.
+pub fn read_fast_update(reader: &mut impl Read) -> Result<(u16, u16), StatusCode> {
+    let mut reply = [0u8; 7];
+
+    reader.read_exact(&mut reply).map_err(io_status)?;
+
+    let (data, crc) = reply.split_at(5);
+
+    if crc16(data).to_be_bytes() != crc {
+        log::debug!("CRC mismatch in fast update reply {:02x?}", data);
+
+        return Err(StatusCode::ErrCommunication);
+    }
+
+    if data[0] != SMCMD_FAST_UPDATE_CYCLE_RET {
+        return Err(StatusCode::ErrCommunication);
+    }
+
+    Ok((
+        u16::from_be_bytes([data[1], data[2]]),
+        u16::from_be_bytes([data[3], data[4]]),
+    ))
+}
+
+/// A received SM485 packet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Packet {
+    pub command: u8,
+    pub address: u8,
+    pub payload: Vec<u8>,
+}
+
+/// Read and validate a single packet from the bus.
+pub fn read_packet(reader: &mut impl Read) -> Result<Packet, StatusCode> {
+    let mut header = [0u8; 3];
+
+    reader.read_exact(&mut header).map_err(io_status)?;
+
+    let [command, len, address] = header;
+
+    if usize::from(len) > MAX_PAYLOAD_BYTES {
+        return Err(StatusCode::ErrCommunication);
+    }
+
+    let mut rest = vec![0u8; usize::from(len) + 2];
+
+    reader.read_exact(&mut rest).map_err(io_status)?;
+
+    let (payload, crc) = rest.split_at(usize::from(len));
+
+    let mut data = header.to_vec();
+    data.extend_from_slice(payload);
+
+    if crc16(&data).to_be_bytes() != crc {
+        log::debug!("CRC mismatch in packet {:02x?}", data);
+
+        return Err(StatusCode::ErrCommunication);
+    }
+
+    Ok(Packet {
+        command,
+        address,
+        payload: payload.to_vec(),
+    })
+}
+
+/// Map an IO error to the equivalent SimpleMotion status.
+///
+/// A timeout means nothing answered, which the C library reports as no device.
+pub fn io_status(error: io::Error) -> StatusCode {
+    log::trace!("Bus IO error: {}", error);
+
+    match error.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock | io::ErrorKind::UnexpectedEof => {
+            StatusCode::ErrNodevice
+        }
+        _ => StatusCode::ErrBus,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc16(b"123456789"), 0x4b37);
+    }
+
+    #[test]
+    fn command_encoding() {
+        let payload = encode_commands(&[
+            Command::SetParamAddr(553),
+            Command::Value24(-1),
+            Command::Value32(100_000),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            payload,
+            [0x82, 0x29, 0x7f, 0xff, 0xff, 0x00, 0x01, 0x86, 0xa0]
+        );
+    }
+
+    #[test]
+    fn return_decoding() {
+        let returns =
+            decode_returns(&[0xc0, 0x00, 0x3f, 0xff, 0xff, 0xff, 0x40, 0x00, 0x05]).unwrap();
+
+        assert_eq!(
+            returns,
+            [
+                Return::CommandStatus(0),
+                Return::Value(-1),
+                Return::Value(5)
+            ]
+        );
+
+        assert_eq!(decode_returns(&[0x00, 0x01]), Err(StatusCode::ErrLength));
+    }
+
+    #[test]
+    fn packet_round_trip() {
+        let packet = encode_packet(SMCMD_INSTANT_CMD_RET, 1, &[0xc0, 0x00]);
+
+        assert_eq!(
+            read_packet(&mut packet.as_slice()),
+            Ok(Packet {
+                command: SMCMD_INSTANT_CMD_RET,
+                address: 1,
+                payload: vec![0xc0, 0x00]
+            })
+        );
+
+        let mut corrupted = packet.clone();
+        corrupted[3] ^= 0xff;
+
+        assert_eq!(
+            read_packet(&mut corrupted.as_slice()),
+            Err(StatusCode::ErrCommunication)
+        );
+    }
+
+    #[test]
+    fn fast_update() {
+        assert_eq!(
+            &encode_fast_update(3, 0x1234, 0xabcd)[..6],
+            [SMCMD_FAST_UPDATE_CYCLE, 3, 0x12, 0x34, 0xab, 0xcd]
+        );
+
+        let mut reply = vec![SMCMD_FAST_UPDATE_CYCLE_RET, 0x00, 0x01, 0xff, 0xfe];
+        reply.extend_from_slice(&crc16(&reply).to_be_bytes());
+
+        assert_eq!(read_fast_update(&mut reply.as_slice()), Ok((1, 0xfffe)));
+    }
+}
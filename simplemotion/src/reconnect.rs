@@ -0,0 +1,128 @@
+//! Automatic reconnection after communication errors.
+
+use crate::StatusCode;
+use std::time::{Duration, Instant};
+
+/// How [`Argon`](crate::Argon) reconnects after a communication error.
+///
+/// Set with [`Argon::set_auto_reconnect`](crate::Argon::set_auto_reconnect). When a transaction
+/// fails with [`StatusCode::ErrCommunication`](crate::StatusCode::ErrCommunication) or
+/// [`StatusCode::ErrBus`](crate::StatusCode::ErrBus), the [shutdown policy](crate::ShutdownPolicy)
+/// is applied first. Then the bus is reopened, the drive configuration is read again and the last
+/// control mode and setpoint are restored. If that works, the failed transaction is retried once.
+///
+/// Reconnecting never blocks. Each call on the `Argon` makes at most one attempt, and calls made
+/// before the next attempt is due fail straight away with the original error. The delay between
+/// attempts starts at `initial_delay` and doubles after every failed attempt, up to `max_delay`.
+/// After `max_attempts` failed attempts, nothing more is tried until
+/// [`Argon::reconnect`](crate::Argon::reconnect) succeeds.
+///
+/// ```rust
+/// use simplemotion::ReconnectPolicy;
+/// use std::time::Duration;
+///
+/// let policy = ReconnectPolicy::new()
+///     .with_initial_delay(Duration::from_millis(50))
+///     .with_max_attempts(5);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    initial_delay: Duration,
+    max_delay: Duration,
+    max_attempts: u32,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReconnectPolicy {
+    /// Up to 10 attempts, starting 100ms apart and backing off to 5s apart.
+    pub fn new() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+            max_attempts: 10,
+        }
+    }
+
+    /// Set the delay after the first failed attempt.
+    pub fn with_initial_delay(mut self, initial_delay: Duration) -> Self {
+        self.initial_delay = initial_delay;
+
+        self
+    }
+
+    /// Set the longest delay between attempts.
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+
+        self
+    }
+
+    /// Set how many attempts are made before giving up.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+
+        self
+    }
+
+    /// Delay after the given failed attempt, counting from 1, or `None` if it was the last one.
+    pub(crate) fn delay(&self, attempt: u32) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+
+        let factor = 1u32.checked_shl(attempt - 1).unwrap_or(u32::MAX);
+
+        Some(
+            self.initial_delay
+                .checked_mul(factor)
+                .map_or(self.max_delay, |delay| delay.min(self.max_delay)),
+        )
+    }
+}
+
+/// Progress of automatic reconnection for one drive.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum ReconnectState {
+    /// No communication error since the last reconnect.
+    Connected,
+
+    /// Waiting to make another attempt.
+    Reconnecting {
+        /// Number of the next attempt, counting from 1.
+        attempt: u32,
+
+        /// When the next attempt is due.
+        next_attempt: Instant,
+
+        /// The error that started reconnecting, returned until reconnected.
+        error: StatusCode,
+    },
+
+    /// Every attempt failed.
+    GaveUp,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff() {
+        let policy = ReconnectPolicy::new()
+            .with_initial_delay(Duration::from_millis(100))
+            .with_max_delay(Duration::from_millis(500))
+            .with_max_attempts(6);
+
+        assert_eq!(
+            (1..=6)
+                .map(|attempt| policy.delay(attempt).map(|d| d.as_millis()))
+                .collect::<Vec<_>>(),
+            [Some(100), Some(200), Some(400), Some(500), Some(500), None]
+        );
+    }
+}
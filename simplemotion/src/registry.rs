@@ -0,0 +1,454 @@
+//! Typed drive parameters.
+//!
+//! Each parameter is a type implementing [`Parameter`], which carries its address, value type,
+//! unit, access and valid range. Use them with [`Argon::get`](crate::Argon::get) and
+//! [`Argon::set`](crate::Argon::set):
+//!
+//! ```rust
+//! use simplemotion::{
+//!     registry::{EncoderPpr, InputMultiplier},
+//!     sim::{Sim, SimDrive},
+//!     Argon,
+//! };
+//!
+//! let sim = Sim::new();
+//! sim.add_drive(1, SimDrive::new());
+//!
+//! let argon = Argon::with_sim(&sim, 1)?;
+//!
+//! let ppr: i32 = argon.get::<EncoderPpr>()?;
+//! argon.set::<InputMultiplier>(2)?;
+//!
+//! // Read only parameters can't be set:
+//! // argon.set::<simplemotion::registry::ActualVelocity>(0)?;
+//! # Ok::<(), simplemotion::Error>(())
+//! ```
+//!
+//! Parameters stored by the drive in fixed-point, e.g. gains and motor constants, use [`Fixed`]
+//! with the scale given in the registry:
+//!
+//! ```rust
+//! # use simplemotion::{registry::MotorResistance, sim::{Sim, SimDrive}, Argon};
+//! # let sim = Sim::new();
+//! # sim.add_drive(1, SimDrive::new());
+//! # let argon = Argon::with_sim(&sim, 1)?;
+//! // Sent to the drive as 1500 mΩ
+//! argon.set::<MotorResistance>(1.5.into())?;
+//! # Ok::<(), simplemotion::Error>(())
+//! ```
+//!
+//! The registry is generated at build time from `simplemotion_defs.h`. Parameters with a known
+//! value type, unit, access and range are listed in `src/registry.in`. Every other parameter
+//! address in the header is added as a read/write `i32` with [`Unit::Unknown`].
+//!
+//! Parameters not listed here can be accessed by address with
+//! [`Argon::read_raw`](crate::Argon::read_raw) and [`Argon::write_raw`](crate::Argon::write_raw).
+
+use crate::defs::*;
+
+/// A drive parameter.
+pub trait Parameter {
+    /// Parameter address.
+    const ADDRESS: u16;
+
+    /// Name of the `SMP_*` define in `simplemotion_defs.h`.
+    const NAME: &'static str;
+
+    /// Unit of the value, after any fixed-point scaling.
+    const UNIT: Unit;
+
+    /// Whether the parameter can be written.
+    const ACCESS: Access;
+
+    /// Smallest valid raw value.
+    const MIN: i32;
+
+    /// Largest valid raw value.
+    const MAX: i32;
+
+    /// Value type.
+    type Value: Value;
+}
+
+/// A parameter that can be written with [`Argon::set`](crate::Argon::set).
+pub trait Writable: Parameter {}
+
+/// Parameter read/write access.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Access {
+    ReadOnly,
+    ReadWrite,
+}
+
+/// Unit of a raw parameter value.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Unit {
+    /// Unitless, e.g. a counter, ratio or mode.
+    None,
+
+    /// Bit field.
+    Bits,
+
+    /// Encoder counts.
+    Counts,
+
+    /// Encoder counts per PID cycle.
+    CountsPerCycle,
+
+    /// Encoder counts per PID cycle squared.
+    CountsPerCycleSquared,
+
+    /// Hertz.
+    Hertz,
+
+    /// Bits per second.
+    Baud,
+
+    /// Milliseconds.
+    Milliseconds,
+
+    /// Milliamps.
+    Milliamps,
+
+    /// Ohms.
+    Ohms,
+
+    /// Henries.
+    Henries,
+
+    /// Kilogram square metres.
+    KilogramSquareMetres,
+
+    /// Newton metres per amp, or newtons per amp for linear motors.
+    NewtonMetresPerAmp,
+
+    /// Device specific scale. See the drive documentation.
+    DeviceScaled,
+
+    /// Not described by this library. See the drive documentation.
+    Unknown,
+}
+
+/// A value that can be converted to and from the raw 32 bit parameter value.
+pub trait Value: Copy {
+    /// Smallest raw value representable by this type.
+    const MIN: i32;
+
+    /// Largest raw value representable by this type.
+    const MAX: i32;
+
+    /// Raw value of `1`. Only [`Fixed`] values are scaled.
+    const SCALE: i32 = 1;
+
+    /// Convert from the raw value read from the drive, or `None` if it isn't valid for this type.
+    fn from_raw(raw: i32) -> Option<Self>;
+
+    /// Convert to the raw value sent to the drive, or `None` if it can't be represented.
+    fn to_raw(self) -> Option<i32>;
+}
+
+/// A fixed-point value, stored by the drive as `value * SCALE` rounded to an integer.
+///
+/// ```rust
+/// use simplemotion::registry::{Fixed, Value};
+///
+/// assert_eq!(Fixed::<1000>(1.5).to_raw(), Some(1500));
+/// assert_eq!(Fixed::<1000>::from_raw(250), Some(Fixed(0.25)));
+/// assert_eq!(Fixed::<1000>(f64::NAN).to_raw(), None);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Default)]
+pub struct Fixed<const SCALE: i32>(pub f64);
+
+impl<const SCALE: i32> Fixed<SCALE> {
+    /// The value in physical units.
+    pub fn value(self) -> f64 {
+        self.0
+    }
+}
+
+impl<const SCALE: i32> From<f64> for Fixed<SCALE> {
+    fn from(value: f64) -> Self {
+        Self(value)
+    }
+}
+
+impl<const SCALE: i32> Value for Fixed<SCALE> {
+    const MIN: i32 = i32::MIN;
+    const MAX: i32 = i32::MAX;
+    const SCALE: i32 = SCALE;
+
+    fn from_raw(raw: i32) -> Option<Self> {
+        Some(Self(f64::from(raw) / f64::from(SCALE)))
+    }
+
+    fn to_raw(self) -> Option<i32> {
+        let raw = (self.0 * f64::from(SCALE)).round();
+
+        // Also rejects NaN
+        (f64::from(i32::MIN)..=f64::from(i32::MAX))
+            .contains(&raw)
+            .then_some(raw as i32)
+    }
+}
+
+impl Value for bool {
+    const MIN: i32 = 0;
+    const MAX: i32 = 1;
+
+    fn from_raw(raw: i32) -> Option<Self> {
+        Some(raw != 0)
+    }
+
+    fn to_raw(self) -> Option<i32> {
+        Some(self.into())
+    }
+}
+
+impl Value for i16 {
+    const MIN: i32 = i16::MIN as i32;
+    const MAX: i32 = i16::MAX as i32;
+
+    /// Uses the lower 16 bits of the raw value.
+    fn from_raw(raw: i32) -> Option<Self> {
+        Some(raw as i16)
+    }
+
+    fn to_raw(self) -> Option<i32> {
+        Some(self.into())
+    }
+}
+
+impl Value for i32 {
+    const MIN: i32 = i32::MIN;
+    const MAX: i32 = i32::MAX;
+
+    fn from_raw(raw: i32) -> Option<Self> {
+        Some(raw)
+    }
+
+    fn to_raw(self) -> Option<i32> {
+        Some(self)
+    }
+}
+
+impl Value for u32 {
+    // The full bit pattern is valid
+    const MIN: i32 = i32::MIN;
+    const MAX: i32 = i32::MAX;
+
+    fn from_raw(raw: i32) -> Option<Self> {
+        Some(raw as u32)
+    }
+
+    fn to_raw(self) -> Option<i32> {
+        Some(self as i32)
+    }
+}
+
+/// Unknown bits are kept.
+impl Value for crate::Faults {
+    const MIN: i32 = i32::MIN;
+    const MAX: i32 = i32::MAX;
+
+    fn from_raw(raw: i32) -> Option<Self> {
+        Some(Self::from(raw as u32))
+    }
+
+    fn to_raw(self) -> Option<i32> {
+        Some(self.bits() as i32)
+    }
+}
+
+/// Unknown bits are kept.
+impl Value for crate::Status {
+    const MIN: i32 = i32::MIN;
+    const MAX: i32 = i32::MAX;
+
+    fn from_raw(raw: i32) -> Option<Self> {
+        Some(Self::from(raw as u32))
+    }
+
+    fn to_raw(self) -> Option<i32> {
+        Some(self.bits() as i32)
+    }
+}
+
+impl Value for crate::ControlMode {
+    const MIN: i32 = CM_TORQUE as i32;
+    const MAX: i32 = CM_NONE as i32;
+
+    fn from_raw(raw: i32) -> Option<Self> {
+        match raw as u32 {
+            CM_TORQUE => Some(Self::Torque),
+            CM_VELOCITY => Some(Self::Velocity),
+            CM_POSITION => Some(Self::Position),
+            CM_NONE => Some(Self::None),
+            _ => None,
+        }
+    }
+
+    fn to_raw(self) -> Option<i32> {
+        Some(self as i32)
+    }
+}
+
+/// Description of a parameter, for tools that list or dump drive parameters.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Info {
+    pub address: u16,
+    pub name: &'static str,
+    pub unit: Unit,
+    pub access: Access,
+    pub min: i32,
+    pub max: i32,
+
+    /// Raw value of `1`, see [`Fixed`].
+    pub scale: i32,
+}
+
+impl Info {
+    /// Get the description of a typed parameter.
+    pub const fn of<P: Parameter>() -> Self {
+        Self {
+            address: P::ADDRESS,
+            name: P::NAME,
+            unit: P::UNIT,
+            access: P::ACCESS,
+            min: P::MIN,
+            max: P::MAX,
+            scale: <P::Value as Value>::SCALE,
+        }
+    }
+
+    /// Whether a raw value is within the valid range.
+    pub fn contains(&self, value: i32) -> bool {
+        (self.min..=self.max).contains(&value)
+    }
+}
+
+macro_rules! parameters {
+    (@access ro) => { Access::ReadOnly };
+    (@access rw) => { Access::ReadWrite };
+    (@writable rw $name:ident) => { impl Writable for $name {} };
+    (@writable ro $name:ident) => {};
+    (@bound $default:expr) => { $default };
+    (@bound $default:expr, $bound:expr) => { $bound };
+
+    ($(
+        $(#[$attr:meta])*
+        $name:ident: $define:ident, $ty:ty, $unit:ident, $access:ident $(, $min:literal..=$max:literal)?;
+    )*) => {
+        $(
+            $(#[$attr])*
+            #[derive(Debug, Copy, Clone)]
+            pub struct $name;
+
+            impl Parameter for $name {
+                const ADDRESS: u16 = $define as u16;
+                const NAME: &'static str = stringify!($define);
+                const UNIT: Unit = Unit::$unit;
+                const ACCESS: Access = parameters!(@access $access);
+                const MIN: i32 = parameters!(@bound <$ty as Value>::MIN $(, $min)?);
+                const MAX: i32 = parameters!(@bound <$ty as Value>::MAX $(, $max)?);
+
+                type Value = $ty;
+            }
+
+            parameters!(@writable $access $name);
+        )*
+
+        /// Every parameter in this module, grouped by function.
+        pub const ALL: &[Info] = &[$(Info::of::<$name>()),*];
+    };
+}
+
+include!(concat!(env!("OUT_DIR"), "/registry.rs"));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        sim::{Sim, SimDrive},
+        Argon, Error,
+    };
+
+    #[test]
+    fn typed_values() {
+        assert_eq!(bool::from_raw(2), Some(true));
+        assert_eq!(i16::from_raw(0x0001_ffff), Some(-1));
+        assert_eq!(u32::from_raw(-1), Some(u32::MAX));
+        assert_eq!(u32::MAX.to_raw(), Some(-1));
+        assert_eq!(Fixed::<1000>(-0.0015).to_raw(), Some(-2));
+        assert_eq!(Fixed::<1000>(3e6).to_raw(), None);
+        assert_eq!(Fixed::<1000>(f64::INFINITY).to_raw(), None);
+        assert_eq!(Fixed::<1_000_000>::from_raw(2500), Some(Fixed(0.0025)));
+        assert_eq!(
+            crate::ControlMode::from_raw(CM_POSITION as i32),
+            Some(crate::ControlMode::Position)
+        );
+        assert_eq!(crate::ControlMode::from_raw(7), None);
+        assert_eq!(
+            crate::Faults::from_raw(FLT_OVERVOLTAGE as i32 | 1 << 30).map(|f| f.to_bits()),
+            Some(FLT_OVERVOLTAGE | 1 << 30)
+        );
+    }
+
+    #[test]
+    fn unique_addresses() {
+        for (i, a) in ALL.iter().enumerate() {
+            for b in &ALL[i + 1..] {
+                assert_ne!(a.address, b.address, "{} and {}", a.name, b.name);
+            }
+        }
+    }
+
+    #[test]
+    fn get_set() {
+        let sim = Sim::new();
+        sim.add_drive(1, SimDrive::new());
+
+        let argon = Argon::with_sim(&sim, 1).unwrap();
+
+        assert_eq!(argon.get::<EncoderPpr>().unwrap(), 2000);
+
+        argon.set::<HomingControl>(true).unwrap();
+        assert!(argon.get::<HomingControl>().unwrap());
+
+        argon
+            .set::<ControlMode>(crate::ControlMode::Position)
+            .unwrap();
+        assert_eq!(
+            argon.get::<ControlMode>().unwrap(),
+            crate::ControlMode::Position
+        );
+        assert!(argon.get::<Status>().unwrap().enabled());
+
+        sim.add_drive(
+            2,
+            SimDrive::new().with_parameter(SMP_CONTROL_MODE as u16, 7),
+        );
+
+        assert!(matches!(
+            Argon::with_sim(&sim, 2).unwrap().get::<ControlMode>(),
+            Err(Error::InvalidValue(address, 7)) if address == SMP_CONTROL_MODE as u16
+        ));
+
+        argon.set::<VelocityP>(Fixed(2.5)).unwrap();
+        assert_eq!(argon.read_raw(SMP_VEL_P as u16).unwrap(), 2500);
+        assert_eq!(argon.get::<VelocityP>().unwrap(), Fixed(2.5));
+        assert_eq!(Info::of::<VelocityP>().scale, 1000);
+
+        assert!(matches!(
+            argon.set::<MotorResistance>(Fixed(f64::NAN)),
+            Err(Error::Unrepresentable("SMP_MOTOR_RES"))
+        ));
+        assert!(matches!(
+            argon.set::<MotorResistance>(Fixed(-1.0)),
+            Err(Error::OutOfRange("SMP_MOTOR_RES", -1000, 0, _))
+        ));
+
+        // Generated from the header
+        assert!(ALL.contains(&Info::of::<BufferSize>()));
+        assert_eq!(BufferSize::UNIT, Unit::Unknown);
+    }
+}
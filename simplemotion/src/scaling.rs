@@ -0,0 +1,379 @@
+//! Conversions between drive units and physical units.
+//!
+//! The drive works in encoder counts and PID cycles:
+//!
+//! - Positions are in encoder counts, after quadrature decoding.
+//! - Velocities are in counts per PID cycle.
+//! - Accelerations are in counts per PID cycle squared.
+//!
+//! Position and velocity setpoints sent to the drive are multiplied by `[MUL]/[DIV]` before use.
+//! Torque setpoints are in mA and aren't scaled. Feedback read from the drive is not scaled.
+//!
+//! Physical units are those of the output of the [`Transmission`], which is the motor shaft
+//! unless one is set.
+
+use crate::{Error, Transmission};
+
+/// Unit conversions for one drive, derived from its configuration.
+///
+/// Read from the drive when it is connected and available from
+/// [`Argon::scaling`](crate::Argon::scaling).
+///
+/// ```rust
+/// use simplemotion::Scaling;
+///
+/// // 2000 PPR encoder, 2500Hz PID loop, no setpoint scaling
+/// let scaling = Scaling::new(2000, 2500, 1, 1, 1000)?;
+///
+/// assert_eq!(scaling.counts_per_rev(), 8000.0);
+/// assert_eq!(scaling.rps_to_counts_per_cycle(10.0), 32.0);
+/// assert_eq!(scaling.counts_to_setpoint(scaling.degrees_to_counts(90.0))?, 2000);
+/// # Ok::<(), simplemotion::Error>(())
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Scaling {
+    /// Encoder counts per revolution.
+    ///
+    /// This is the encoder PPR multiplied by 4 to account for quadrature encoders.
+    counts_per_rev: f64,
+
+    /// PID loop frequency in Hz.
+    pid_frequency: f64,
+
+    /// Read from `[MUL]`
+    input_mul: f64,
+
+    /// Read from `[DIV]`
+    input_div: f64,
+
+    /// Read from `[CVL]`, in counts per PID cycle.
+    velocity_limit: f64,
+
+    /// Mechanics between the motor and the output.
+    transmission: Transmission,
+}
+
+impl Scaling {
+    /// Create a scaling from drive configuration values.
+    ///
+    /// `velocity_limit` is the trajectory planner velocity limit `[CVL]` in counts per PID cycle.
+    ///
+    /// Fails with [`Error::InvalidScaling`] if the encoder PPR, PID frequency, `[MUL]` or `[DIV]`
+    /// is not positive.
+    pub fn new(
+        encoder_ppr: i32,
+        pid_frequency: i32,
+        input_mul: i32,
+        input_div: i32,
+        velocity_limit: i32,
+    ) -> Result<Self, Error> {
+        for (name, value) in [
+            ("encoder PPR", encoder_ppr),
+            ("PID frequency", pid_frequency),
+            ("[MUL]", input_mul),
+            ("[DIV]", input_div),
+        ] {
+            if value <= 0 {
+                return Err(Error::InvalidScaling(name, value));
+            }
+        }
+
+        Ok(Self {
+            counts_per_rev: f64::from(encoder_ppr) * 4.0,
+            pid_frequency: pid_frequency.into(),
+            input_mul: input_mul.into(),
+            input_div: input_div.into(),
+            velocity_limit: velocity_limit.into(),
+            transmission: Transmission::new(),
+        })
+    }
+
+    /// Placeholder used until the drive configuration has been read. Every conversion is invalid.
+    pub(crate) fn unread() -> Self {
+        Self {
+            counts_per_rev: 0.0,
+            pid_frequency: 0.0,
+            input_mul: 0.0,
+            input_div: 0.0,
+            velocity_limit: 0.0,
+            transmission: Transmission::new(),
+        }
+    }
+
+    /// Set the mechanics between the motor and the output.
+    pub fn with_transmission(mut self, transmission: Transmission) -> Self {
+        self.transmission = transmission;
+
+        self
+    }
+
+    /// Mechanics between the motor and the output.
+    pub fn transmission(&self) -> &Transmission {
+        &self.transmission
+    }
+
+    /// Encoder counts per motor revolution.
+    pub fn counts_per_rev(&self) -> f64 {
+        self.counts_per_rev
+    }
+
+    /// Signed encoder counts per output revolution.
+    fn counts_per_output_rev(&self) -> f64 {
+        self.counts_per_rev * self.transmission.motor_revs_per_rev()
+    }
+
+    /// PID loop frequency in Hz.
+    pub fn pid_frequency(&self) -> f64 {
+        self.pid_frequency
+    }
+
+    /// Fastest output velocity allowed by the trajectory planner `[CVL]`, in RPS.
+    pub fn velocity_limit_rps(&self) -> f64 {
+        self.counts_per_cycle_to_rps(self.velocity_limit).abs()
+    }
+
+    // Setpoints
+
+    /// Convert a position in counts or velocity in counts per cycle to the setpoint that commands
+    /// it.
+    ///
+    /// Fails with [`Error::SetpointOutOfRange`] if the setpoint is not finite or doesn't fit in
+    /// an `i32`.
+    pub fn counts_to_setpoint(&self, counts: f64) -> Result<i32, Error> {
+        let setpoint = (counts * self.input_div / self.input_mul).round();
+
+        // Also rejects NaN
+        if (f64::from(i32::MIN)..=f64::from(i32::MAX)).contains(&setpoint) {
+            Ok(setpoint as i32)
+        } else {
+            Err(Error::SetpointOutOfRange(counts))
+        }
+    }
+
+    /// Convert a setpoint to the position in counts or velocity in counts per cycle it commands.
+    pub fn setpoint_to_counts(&self, setpoint: i32) -> f64 {
+        f64::from(setpoint) * self.input_mul / self.input_div
+    }
+
+    // Position
+
+    /// Convert degrees to encoder counts.
+    pub fn degrees_to_counts(&self, degrees: f64) -> f64 {
+        degrees * self.counts_per_output_rev() / 360.0
+    }
+
+    /// Convert encoder counts to degrees.
+    pub fn counts_to_degrees(&self, counts: f64) -> f64 {
+        counts * 360.0 / self.counts_per_output_rev()
+    }
+
+    /// Convert revolutions to encoder counts.
+    pub fn revs_to_counts(&self, revs: f64) -> f64 {
+        revs * self.counts_per_output_rev()
+    }
+
+    /// Convert encoder counts to revolutions.
+    pub fn counts_to_revs(&self, counts: f64) -> f64 {
+        counts / self.counts_per_output_rev()
+    }
+
+    /// Convert millimetres to encoder counts.
+    ///
+    /// Requires a [screw pitch](Transmission::with_screw_pitch).
+    pub fn mm_to_counts(&self, mm: f64) -> Result<f64, Error> {
+        Ok(self.revs_to_counts(mm / self.screw_pitch()?))
+    }
+
+    /// Convert encoder counts to millimetres.
+    ///
+    /// Requires a [screw pitch](Transmission::with_screw_pitch).
+    pub fn counts_to_mm(&self, counts: f64) -> Result<f64, Error> {
+        Ok(self.counts_to_revs(counts) * self.screw_pitch()?)
+    }
+
+    fn screw_pitch(&self) -> Result<f64, Error> {
+        self.transmission.screw_pitch().ok_or(Error::NoScrewPitch)
+    }
+
+    // Velocity
+
+    /// Convert revolutions per second to counts per PID cycle.
+    pub fn rps_to_counts_per_cycle(&self, rps: f64) -> f64 {
+        rps * self.counts_per_output_rev() / self.pid_frequency
+    }
+
+    /// Convert counts per PID cycle to revolutions per second.
+    pub fn counts_per_cycle_to_rps(&self, counts_per_cycle: f64) -> f64 {
+        counts_per_cycle * self.pid_frequency / self.counts_per_output_rev()
+    }
+
+    /// Convert revolutions per minute to counts per PID cycle.
+    pub fn rpm_to_counts_per_cycle(&self, rpm: f64) -> f64 {
+        self.rps_to_counts_per_cycle(rpm / 60.0)
+    }
+
+    /// Convert counts per PID cycle to revolutions per minute.
+    pub fn counts_per_cycle_to_rpm(&self, counts_per_cycle: f64) -> f64 {
+        self.counts_per_cycle_to_rps(counts_per_cycle) * 60.0
+    }
+
+    // Acceleration
+
+    /// Convert revolutions per second squared to counts per PID cycle squared.
+    pub fn rps2_to_counts_per_cycle2(&self, rps2: f64) -> f64 {
+        rps2 * self.counts_per_output_rev() / (self.pid_frequency * self.pid_frequency)
+    }
+
+    /// Convert counts per PID cycle squared to revolutions per second squared.
+    pub fn counts_per_cycle2_to_rps2(&self, counts_per_cycle2: f64) -> f64 {
+        counts_per_cycle2 * self.pid_frequency * self.pid_frequency / self.counts_per_output_rev()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    /// Argon defaults: 2000 PPR, 2500Hz, no setpoint scaling.
+    #[test]
+    fn default_config() {
+        let scaling = Scaling::new(2000, 2500, 1, 1, 1000).unwrap();
+
+        assert_eq!(scaling.counts_per_rev(), 8000.0);
+        assert_eq!(scaling.degrees_to_counts(90.0), 2000.0);
+        assert_eq!(scaling.counts_to_revs(-4000.0), -0.5);
+        assert_eq!(scaling.counts_to_setpoint(2000.0).unwrap(), 2000);
+
+        // 10 RPS = 80000 counts/s = 32 counts per 400us cycle
+        assert_eq!(scaling.rps_to_counts_per_cycle(10.0), 32.0);
+        assert_eq!(scaling.counts_per_cycle_to_rpm(32.0), 600.0);
+        assert_eq!(scaling.velocity_limit_rps(), 312.5);
+
+        // 1 rev/s² = 8000 counts/s² = 0.00128 counts per cycle²
+        assert!(close(scaling.rps2_to_counts_per_cycle2(1.0), 0.00128));
+        assert!(close(scaling.counts_per_cycle2_to_rps2(0.00128), 1.0));
+
+        assert!(matches!(
+            scaling.mm_to_counts(1.0),
+            Err(Error::NoScrewPitch)
+        ));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(matches!(
+            Scaling::new(0, 2500, 1, 1, 1000),
+            Err(Error::InvalidScaling("encoder PPR", 0))
+        ));
+        assert!(matches!(
+            Scaling::new(2000, 2500, 1, -5, 1000),
+            Err(Error::InvalidScaling("[DIV]", -5))
+        ));
+
+        let scaling = Scaling::new(2000, 2500, 1, 1000, 1000).unwrap();
+
+        assert_eq!(
+            scaling.counts_to_setpoint(2_000_000.0).unwrap(),
+            2_000_000_000
+        );
+        assert!(matches!(
+            scaling.counts_to_setpoint(3_000_000.0),
+            Err(Error::SetpointOutOfRange(_))
+        ));
+        assert!(scaling.counts_to_setpoint(f64::NAN).is_err());
+        assert!(scaling.counts_to_setpoint(f64::NEG_INFINITY).is_err());
+    }
+
+    /// High resolution encoder with a 10kHz loop and setpoints scaled by `[MUL]/[DIV]` = 4/5.
+    #[test]
+    fn scaled_setpoints() {
+        let scaling = Scaling::new(10_000, 10_000, 4, 5, 400)
+            .unwrap()
+            .with_transmission(Transmission::new().with_screw_pitch(5.0));
+
+        assert_eq!(scaling.counts_per_rev(), 40_000.0);
+
+        // 5mm pitch: 1mm = 0.2 rev = 8000 counts, commanded with a setpoint of 10000
+        assert_eq!(scaling.mm_to_counts(1.0).unwrap(), 8000.0);
+        assert_eq!(scaling.counts_to_setpoint(8000.0).unwrap(), 10_000);
+        assert_eq!(scaling.setpoint_to_counts(10_000), 8000.0);
+        assert_eq!(scaling.counts_to_mm(-40_000.0).unwrap(), -5.0);
+
+        // 3000 RPM = 50 RPS = 2M counts/s = 200 counts per cycle
+        assert_eq!(scaling.rpm_to_counts_per_cycle(3000.0), 200.0);
+        assert_eq!(scaling.counts_to_setpoint(200.0).unwrap(), 250);
+        assert_eq!(scaling.velocity_limit_rps(), 100.0);
+    }
+
+    /// Velocity setpoints before and after `Argon::set_velocity_rps` moved to `Scaling`, with
+    /// `[MUL]/[DIV]` = 4/5.
+    #[test]
+    fn velocity_setpoint_change() {
+        let (ppr, pid_frequency, mul, div) = (2000, 2500, 4, 5);
+        let scaling = Scaling::new(ppr, pid_frequency, mul, div, 1000).unwrap();
+
+        // 10 RPS = 32 counts per cycle, commanded by 32 * [DIV] / [MUL]
+        let setpoint = scaling
+            .counts_to_setpoint(scaling.rps_to_counts_per_cycle(10.0))
+            .unwrap();
+
+        assert_eq!(setpoint, 40);
+        assert!(close(
+            scaling.counts_per_cycle_to_rps(scaling.setpoint_to_counts(setpoint)),
+            10.0
+        ));
+
+        // Previously `rps * counts_per_rev * [MUL] / pid_frequency`, and the setpoint read back
+        // as `setpoint * pid_frequency / counts_per_rev / [DIV]`
+        let counts_per_rev = f64::from(ppr) * 4.0;
+        let old_setpoint =
+            (10.0 * counts_per_rev * f64::from(mul) / f64::from(pid_frequency)).round() as i32;
+
+        assert_eq!(old_setpoint, 128);
+        assert_eq!(
+            f64::from(old_setpoint) * f64::from(pid_frequency) / counts_per_rev / f64::from(div),
+            8.0
+        );
+
+        // Both agree without setpoint scaling
+        let unscaled = Scaling::new(ppr, pid_frequency, 1, 1, 1000).unwrap();
+
+        assert_eq!(
+            unscaled
+                .counts_to_setpoint(unscaled.rps_to_counts_per_cycle(10.0))
+                .unwrap(),
+            (10.0 * counts_per_rev / f64::from(pid_frequency)).round() as i32
+        );
+    }
+
+    /// 3:1 belt reduction onto a spindle, with the motor turning the opposite way.
+    #[test]
+    fn transmission() {
+        let scaling = Scaling::new(2000, 2500, 1, 1, 1000)
+            .unwrap()
+            .with_transmission(
+                Transmission::new()
+                    .with_gear_ratio(3.0)
+                    .unwrap()
+                    .with_inverted(true),
+            );
+
+        // Motor encoder counts are unchanged
+        assert_eq!(scaling.counts_per_rev(), 8000.0);
+
+        // One spindle revolution is three motor revolutions backwards
+        assert_eq!(scaling.revs_to_counts(1.0), -24_000.0);
+        assert_eq!(scaling.degrees_to_counts(90.0), -6000.0);
+        assert_eq!(scaling.counts_to_degrees(-6000.0), 90.0);
+
+        // 1000 spindle RPM is 3000 motor RPM = 160 counts per cycle
+        assert_eq!(scaling.rpm_to_counts_per_cycle(1000.0), -160.0);
+        assert!(close(scaling.counts_per_cycle_to_rpm(-160.0), 1000.0));
+        assert!(close(scaling.velocity_limit_rps(), 312.5 / 3.0));
+        assert!(close(scaling.rps2_to_counts_per_cycle2(1.0), -0.00384));
+    }
+}
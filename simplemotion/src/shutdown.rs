@@ -0,0 +1,22 @@
+//! What to do with the motor when the host stops controlling it.
+
+/// Action taken by [`Argon`](crate::Argon) when it is dropped, including while unwinding from a
+/// panic, and when a communication error is detected.
+///
+/// Set with [`Argon::set_shutdown_policy`](crate::Argon::set_shutdown_policy).
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ShutdownPolicy {
+    /// Command zero velocity or torque. In position mode, the actual position is written back as
+    /// the setpoint so the motor holds where it is.
+    ZeroSetpoint,
+
+    /// Trigger a quick stop. The drive holds the motor until the quick stop is released.
+    QuickStop,
+
+    /// Disable the drive. The motor is unpowered and coasts to a stop.
+    Disable,
+
+    /// Leave the drive running with its current setpoint.
+    #[default]
+    LeaveRunning,
+}
@@ -0,0 +1,689 @@
+//! Simulated drives for testing without hardware.
+//!
+//! A [`Sim`] is a bus of [`SimDrive`]s which answer parameter reads and writes like an Argon
+//! would. Motor velocity follows the setpoint with a first order response, so code that waits for
+//! the motor to reach speed or stop can be tested too. In torque mode the motor is assumed to be
+//! stalled against a load, so it draws the commanded current without moving.
+//!
+//! Simulated time advances by a fixed amount for every bus transaction, and can be advanced
+//! manually with [`Sim::advance`].
+//!
+//! ```
+//! use simplemotion::{
+//!     sim::{Sim, SimDrive},
+//!     Argon, ControlMode,
+//! };
+//! use std::time::Duration;
+//!
+//! let sim = Sim::new();
+//! sim.add_drive(1, SimDrive::new());
+//!
+//! let mut argon = Argon::with_sim(&sim, 1)?;
+//!
+//! argon.set_control_mode(ControlMode::Velocity)?;
+//! argon.set_velocity_rps(10.0)?;
+//!
+//! sim.advance(Duration::from_secs(1));
+//!
+//! assert!((argon.velocity_rps()? - 10.0).abs() < 0.5);
+//! # Ok::<(), simplemotion::Error>(())
+//! ```
+
+use crate::{
+    batch::Op,
+    defs::{
+        CM_POSITION, CM_TORQUE, CM_VELOCITY, FLT_HOST_COMM_ERROR, SMP_ABSOLUTE_SETPOINT,
+        SMP_ACC_FF, SMP_ACTUAL_BUS_VOLTAGE, SMP_ACTUAL_POSITION_FB, SMP_ACTUAL_TORQUE,
+        SMP_ACTUAL_VELOCITY_FB, SMP_ADDRESS_BITS_MASK, SMP_ATTRIBUTE_BITS_MASK, SMP_BUS_SPEED,
+        SMP_CB1_ENABLE, SMP_CB1_QUICKSTOP_RELEASE, SMP_CB1_QUICKSTOP_SET,
+        SMP_CONTINUOUS_CURRENT_LIMIT, SMP_CONTROL_BITS1, SMP_CONTROL_MODE, SMP_DEVICE_TEMPERATURE,
+        SMP_DEVICE_TYPE, SMP_ENCODER_PPR, SMP_FAST_UPDATE_CYCLE_FORMAT, SMP_FAULTS,
+        SMP_FAULT_BEHAVIOR, SMP_FB1_DEVICE_SELECTION, SMP_FIRMWARE_VERSION,
+        SMP_FOLLOWERROR_TRESHOLD, SMP_HOMING_CONTROL, SMP_INPUT_DIVIDER, SMP_INPUT_MULTIPLIER,
+        SMP_MAX_VALUE_MASK, SMP_MIN_VALUE_MASK, SMP_MOTOR_IND, SMP_MOTOR_INERTIA,
+        SMP_MOTOR_POLEPAIRS, SMP_MOTOR_RES, SMP_MOTOR_TORQUE_OR_FORCE_CONSTANT, SMP_MOTOR_TYPE,
+        SMP_OVERSPEED_FAULT_LIMIT, SMP_PEAK_CURRENT_LIMIT, SMP_PID_FREQUENCY, SMP_POS_FF,
+        SMP_POS_P, SMP_SERIAL_NR, SMP_SM_VERSION, SMP_STATUS, SMP_SYSTEM_CONTROL,
+        SMP_SYSTEM_CONTROL_SAVECFG, SMP_TIMEOUT, SMP_TORQUE_I, SMP_TORQUE_P,
+        SMP_TRAJ_PLANNER_ACCEL, SMP_TRAJ_PLANNER_HOMING_OFFSET, SMP_TRAJ_PLANNER_VEL,
+        SMP_VALUE_MASK, SMP_VEL_FF, SMP_VEL_I, SMP_VEL_P, STAT_ENABLED, STAT_FAULTSTOP,
+        STAT_HOMING, STAT_INITIALIZED, STAT_QUICK_STOP_ACTIVE, STAT_RUN, STAT_SERVO_READY,
+        STAT_STANDING_STILL, STAT_TARGET_REACHED, STAT_VOLTAGES_OK,
+    },
+    fast_update::FastUpdateFormat,
+    Faults, StatusCode,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+/// Largest time step used when integrating the motor model.
+const MAX_STEP: Duration = Duration::from_millis(1);
+
+/// Velocity below which the motor is considered to be standing still, in counts per PID cycle.
+const STANDSTILL_VELOCITY: f64 = 0.5;
+
+/// Distance from the setpoint within which a position move is complete, in counts.
+const POSITION_TOLERANCE: f64 = 2.0;
+
+/// A simulated drive and motor.
+#[derive(Debug, Clone)]
+pub struct SimDrive {
+    /// Stored values for parameters without any special behaviour.
+    parameters: BTreeMap<u16, i32>,
+
+    /// Valid `(min, max)` range of parameters written over the bus. Parameters without an entry
+    /// accept any value.
+    ranges: BTreeMap<u16, (i32, i32)>,
+
+    /// Active faults, as `FLT_*` bits.
+    faults: u32,
+
+    /// Quick stop latched by `SMP_CB1_QUICKSTOP_SET`, until `SMP_CB1_QUICKSTOP_RELEASE`.
+    quick_stop: bool,
+
+    /// Motor velocity in encoder counts per PID cycle.
+    velocity: f64,
+
+    /// Motor position in encoder counts.
+    position: f64,
+
+    /// Time left until homing completes, if homing is in progress.
+    homing_remaining: Option<Duration>,
+
+    /// Time since the drive last received a packet, for the `SMP_TIMEOUT` watchdog.
+    since_packet: Duration,
+
+    /// Time constant of the motor velocity response.
+    time_constant: Duration,
+
+    /// How long the homing sequence takes.
+    homing_duration: Duration,
+
+    /// Number of times the configuration was saved to flash.
+    saves: usize,
+
+    /// Parameters that fail every write over the bus.
+    rejected: BTreeSet<u16>,
+}
+
+impl Default for SimDrive {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimDrive {
+    /// Create a new simulated drive in velocity mode with a 2000 PPR encoder, 2500Hz PID loop and
+    /// a 20ms motor time constant.
+    pub fn new() -> Self {
+        let parameters = [
+            (SMP_PID_FREQUENCY, 2500),
+            (SMP_ENCODER_PPR, 2000),
+            (SMP_TRAJ_PLANNER_VEL, 1000),
+            (SMP_TRAJ_PLANNER_ACCEL, 10),
+            (SMP_INPUT_MULTIPLIER, 1),
+            (SMP_INPUT_DIVIDER, 1),
+            (SMP_CONTROL_MODE, CM_VELOCITY as i32),
+            (SMP_ABSOLUTE_SETPOINT, 0),
+            (SMP_HOMING_CONTROL, 0),
+            (SMP_TRAJ_PLANNER_HOMING_OFFSET, 0),
+            // Enabled at power up
+            (SMP_CONTROL_BITS1, SMP_CB1_ENABLE as i32),
+            (SMP_BUS_SPEED, 460_800),
+            (SMP_CONTINUOUS_CURRENT_LIMIT, 5000),
+            (SMP_PEAK_CURRENT_LIMIT, 10_000),
+            (SMP_FAST_UPDATE_CYCLE_FORMAT, 0),
+            (SMP_TIMEOUT, 0),
+            (SMP_FOLLOWERROR_TRESHOLD, 1000),
+            (SMP_OVERSPEED_FAULT_LIMIT, 2000),
+            (SMP_FAULT_BEHAVIOR, 0),
+            (SMP_MOTOR_TYPE, 0),
+            (SMP_MOTOR_POLEPAIRS, 4),
+            (SMP_MOTOR_TORQUE_OR_FORCE_CONSTANT, 0),
+            (SMP_MOTOR_RES, 0),
+            (SMP_MOTOR_IND, 0),
+            (SMP_MOTOR_INERTIA, 0),
+            (SMP_FB1_DEVICE_SELECTION, 0),
+            (SMP_POS_P, 0),
+            (SMP_POS_FF, 0),
+            (SMP_VEL_P, 0),
+            (SMP_VEL_I, 0),
+            (SMP_VEL_FF, 0),
+            (SMP_ACC_FF, 0),
+            (SMP_TORQUE_P, 0),
+            (SMP_TORQUE_I, 0),
+            (SMP_SYSTEM_CONTROL, 0),
+            (SMP_ACTUAL_BUS_VOLTAGE, 48),
+            (SMP_DEVICE_TEMPERATURE, 35),
+            // Placeholder identification, override with `with_parameter` if it matters
+            (SMP_DEVICE_TYPE, 0),
+            (SMP_FIRMWARE_VERSION, 0),
+            (SMP_SERIAL_NR, 0),
+            (SMP_SM_VERSION, 0),
+        ]
+        .into_iter()
+        .map(|(parameter, value)| (parameter as u16, value))
+        .collect();
+
+        Self {
+            parameters,
+            ranges: BTreeMap::new(),
+            faults: 0,
+            quick_stop: false,
+            velocity: 0.0,
+            position: 0.0,
+            homing_remaining: None,
+            since_packet: Duration::ZERO,
+            time_constant: Duration::from_millis(20),
+            homing_duration: Duration::from_millis(200),
+            saves: 0,
+            rejected: BTreeSet::new(),
+        }
+    }
+
+    /// Set the time constant of the first order motor velocity response.
+    pub fn with_time_constant(mut self, time_constant: Duration) -> Self {
+        self.time_constant = time_constant;
+
+        self
+    }
+
+    /// Set how long a homing sequence takes to complete.
+    pub fn with_homing_duration(mut self, homing_duration: Duration) -> Self {
+        self.homing_duration = homing_duration;
+
+        self
+    }
+
+    /// Set the raw value of a parameter.
+    pub fn with_parameter(mut self, parameter: u16, value: i32) -> Self {
+        self.store(parameter, value);
+
+        self
+    }
+
+    /// Limit the values a parameter accepts over the bus. Writes outside the range fail with
+    /// [`StatusCode::ErrParameter`].
+    pub fn with_range(mut self, parameter: u16, min: i32, max: i32) -> Self {
+        self.ranges.insert(parameter, (min, max));
+
+        self
+    }
+
+    /// Raise faults. The drive stops until the faults are cleared.
+    pub fn raise_faults(&mut self, faults: Faults) {
+        self.faults |= faults.to_bits();
+    }
+
+    /// Motor velocity in encoder counts per PID cycle.
+    pub fn velocity(&self) -> f64 {
+        self.velocity
+    }
+
+    /// Motor position in encoder counts.
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    pub(crate) fn parameter(&self, parameter: u32) -> i32 {
+        self.parameters
+            .get(&(parameter as u16))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Make every write to a parameter fail with [`StatusCode::ErrParameter`].
+    #[cfg(test)]
+    pub(crate) fn reject_writes(mut self, parameter: u16) -> Self {
+        self.rejected.insert(parameter);
+
+        self
+    }
+
+    /// Number of times the configuration was saved to flash.
+    #[cfg(test)]
+    pub(crate) fn saves(&self) -> usize {
+        self.saves
+    }
+
+    /// Time since the drive last received a packet.
+    #[cfg(test)]
+    pub(crate) fn since_packet(&self) -> Duration {
+        self.since_packet
+    }
+
+    fn status(&self) -> u32 {
+        let mut status = STAT_INITIALIZED | STAT_VOLTAGES_OK | STAT_SERVO_READY;
+
+        if self.faults != 0 {
+            status |= STAT_FAULTSTOP;
+        } else if self.enabled() {
+            status |= STAT_ENABLED | STAT_RUN;
+        }
+
+        if self.quick_stop {
+            status |= STAT_QUICK_STOP_ACTIVE;
+        }
+
+        if self.homing_remaining.is_some() {
+            status |= STAT_HOMING;
+        }
+
+        if self.velocity.abs() < STANDSTILL_VELOCITY {
+            status |= STAT_STANDING_STILL;
+        }
+
+        let target_reached = match self.parameter(SMP_CONTROL_MODE) as u32 {
+            CM_POSITION => {
+                (f64::from(self.parameter(SMP_ABSOLUTE_SETPOINT)) - self.position).abs()
+                    < POSITION_TOLERANCE
+                    && self.velocity.abs() < STANDSTILL_VELOCITY
+            }
+            _ => (self.target_velocity() - self.velocity).abs() < STANDSTILL_VELOCITY,
+        };
+
+        if target_reached && self.homing_remaining.is_none() {
+            status |= STAT_TARGET_REACHED;
+        }
+
+        status
+    }
+
+    fn enabled(&self) -> bool {
+        self.faults == 0 && self.parameter(SMP_CONTROL_BITS1) as u32 & SMP_CB1_ENABLE != 0
+    }
+
+    /// Velocity the motor is heading towards, in counts per PID cycle.
+    fn target_velocity(&self) -> f64 {
+        if !self.enabled() || self.quick_stop || self.homing_remaining.is_some() {
+            return 0.0;
+        }
+
+        let setpoint = f64::from(self.parameter(SMP_ABSOLUTE_SETPOINT));
+
+        match self.parameter(SMP_CONTROL_MODE) as u32 {
+            CM_VELOCITY => {
+                setpoint * f64::from(self.parameter(SMP_INPUT_MULTIPLIER))
+                    / f64::from(self.parameter(SMP_INPUT_DIVIDER))
+            }
+            // Proportional position controller with the same time constant as the motor
+            CM_POSITION => {
+                let cycles = self.time_constant.as_secs_f64() * self.pid_frequency();
+
+                (setpoint - self.position) / cycles.max(1.0)
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Motor current in mA.
+    fn current(&self) -> f64 {
+        if !self.enabled()
+            || self.quick_stop
+            || self.parameter(SMP_CONTROL_MODE) as u32 != CM_TORQUE
+        {
+            return 0.0;
+        }
+
+        // Torque setpoints are in mA, without `[MUL]/[DIV]` scaling
+        let setpoint = f64::from(self.parameter(SMP_ABSOLUTE_SETPOINT));
+        let limit = f64::from(self.parameter(SMP_PEAK_CURRENT_LIMIT));
+
+        setpoint.clamp(-limit, limit)
+    }
+
+    fn pid_frequency(&self) -> f64 {
+        f64::from(self.parameter(SMP_PID_FREQUENCY))
+    }
+
+    fn read(&self, parameter: u16) -> Result<i32, StatusCode> {
+        let attribute = u32::from(parameter) & SMP_ATTRIBUTE_BITS_MASK;
+        let parameter = parameter & SMP_ADDRESS_BITS_MASK as u16;
+
+        if attribute != SMP_VALUE_MASK {
+            // Make sure the parameter exists
+            self.read(parameter)?;
+
+            let (min, max) = self
+                .ranges
+                .get(&parameter)
+                .copied()
+                .unwrap_or((i32::MIN, i32::MAX));
+
+            return match attribute {
+                SMP_MIN_VALUE_MASK => Ok(min),
+                SMP_MAX_VALUE_MASK => Ok(max),
+                _ => Err(StatusCode::ErrParameter),
+            };
+        }
+
+        match u32::from(parameter) {
+            SMP_STATUS => Ok(self.status() as i32),
+            SMP_FAULTS => Ok(self.faults as i32),
+            // The drive only reports the lower 16 bits of the velocity
+            SMP_ACTUAL_VELOCITY_FB => Ok(i32::from(self.velocity.round() as i16)),
+            SMP_ACTUAL_POSITION_FB => Ok(self.position.round() as i32),
+            SMP_ACTUAL_TORQUE => Ok(self.current().round() as i32),
+            _ => self
+                .parameters
+                .get(&parameter)
+                .copied()
+                .ok_or(StatusCode::ErrParameter),
+        }
+    }
+
+    fn write(&mut self, parameter: u16, value: i32) -> Result<(), StatusCode> {
+        if u32::from(parameter) & SMP_ATTRIBUTE_BITS_MASK != SMP_VALUE_MASK
+            || self.rejected.contains(&parameter)
+        {
+            return Err(StatusCode::ErrParameter);
+        }
+
+        if let Some((min, max)) = self.ranges.get(&parameter) {
+            if !(min..=max).contains(&&value) {
+                return Err(StatusCode::ErrParameter);
+            }
+        }
+
+        self.store(parameter, value);
+
+        Ok(())
+    }
+
+    /// Set a parameter, including any side effects of writing it.
+    fn store(&mut self, parameter: u16, value: i32) {
+        match u32::from(parameter) {
+            SMP_FAULTS => self.faults = value as u32,
+            SMP_CONTROL_BITS1 => {
+                let value = value as u32;
+
+                if value & SMP_CB1_QUICKSTOP_SET != 0 {
+                    self.quick_stop = true;
+                } else if value & SMP_CB1_QUICKSTOP_RELEASE != 0 {
+                    self.quick_stop = false;
+                }
+            }
+            SMP_HOMING_CONTROL => {
+                self.homing_remaining = (value == 1).then_some(self.homing_duration);
+            }
+            // Commands run straight away and aren't stored
+            SMP_SYSTEM_CONTROL => {
+                if value == SMP_SYSTEM_CONTROL_SAVECFG as i32 {
+                    self.saves += 1;
+                }
+
+                return;
+            }
+            _ => (),
+        }
+
+        self.parameters.insert(parameter, value);
+    }
+
+    fn fast_update_cycle(&mut self, out1: u16, out2: u16) -> Result<(u16, u16), StatusCode> {
+        let format = FastUpdateFormat::from_raw(self.parameter(SMP_FAST_UPDATE_CYCLE_FORMAT))
+            .ok_or(StatusCode::ErrParameter)?;
+
+        let (setpoint, _control) = format.decode_command(out1, out2);
+
+        self.store(SMP_ABSOLUTE_SETPOINT as u16, setpoint);
+
+        Ok(format.encode_feedback(self.position.round() as i32, self.status()))
+    }
+
+    /// Advance the simulation.
+    pub fn advance(&mut self, duration: Duration) {
+        let mut remaining = duration;
+
+        while !remaining.is_zero() {
+            let step = remaining.min(MAX_STEP);
+
+            self.step(step);
+
+            remaining -= step;
+        }
+    }
+
+    fn step(&mut self, step: Duration) {
+        self.since_packet += step;
+
+        let timeout = self.parameter(SMP_TIMEOUT);
+
+        if timeout > 0 && self.since_packet > Duration::from_millis(timeout as u64) {
+            self.faults |= FLT_HOST_COMM_ERROR;
+        }
+
+        let target = self.target_velocity();
+
+        let response = 1.0 - (-step.as_secs_f64() / self.time_constant.as_secs_f64()).exp();
+
+        self.velocity += (target - self.velocity) * response;
+        self.position += self.velocity * step.as_secs_f64() * self.pid_frequency();
+
+        if let Some(remaining) = self.homing_remaining {
+            if remaining <= step {
+                // Homing moves to the index pulse plus offset, where the index is at position
+                // zero, then holds that position.
+                let offset = self.parameter(SMP_TRAJ_PLANNER_HOMING_OFFSET);
+
+                self.parameters.insert(SMP_ABSOLUTE_SETPOINT as u16, offset);
+                self.position = f64::from(offset);
+                self.velocity = 0.0;
+                self.homing_remaining = None;
+            } else {
+                self.homing_remaining = Some(remaining - step);
+            }
+        }
+    }
+}
+
+/// A simulated bus with any number of [`SimDrive`]s attached.
+///
+/// Clones share the same bus, so a test can keep a `Sim` to inspect or change drive state while
+/// an [`Argon`](crate::Argon) talks to it.
+#[derive(Debug, Clone, Default)]
+pub struct Sim {
+    inner: Arc<Mutex<SimBus>>,
+}
+
+#[derive(Debug, Default)]
+struct SimBus {
+    drives: BTreeMap<u8, SimDrive>,
+
+    /// Simulated time taken by each bus transaction.
+    transaction_time: Duration,
+
+    /// Every status returned since the last reset, OR'd together.
+    cumulative_status: u32,
+
+    /// Number of upcoming transactions that fail.
+    failures: usize,
+}
+
+impl Sim {
+    /// Create an empty bus where each transaction takes 1ms.
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(SimBus {
+                transaction_time: Duration::from_millis(1),
+                ..SimBus::default()
+            })),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SimBus> {
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Attach a drive at the given address, replacing any drive already there.
+    pub fn add_drive(&self, address: u8, drive: SimDrive) {
+        self.lock().drives.insert(address, drive);
+    }
+
+    /// Set how much simulated time each bus transaction takes.
+    pub fn set_transaction_time(&self, transaction_time: Duration) {
+        self.lock().transaction_time = transaction_time;
+    }
+
+    /// Inspect or modify the drive at the given address.
+    ///
+    /// Returns `None` if there is no drive at that address.
+    pub fn with_drive<R>(&self, address: u8, f: impl FnOnce(&mut SimDrive) -> R) -> Option<R> {
+        self.lock().drives.get_mut(&address).map(f)
+    }
+
+    /// Make the next `count` transactions fail with [`StatusCode::ErrCommunication`], as if the
+    /// replies were lost.
+    pub fn fail_transactions(&self, count: usize) {
+        self.lock().failures = count;
+    }
+
+    /// Advance simulated time for every drive on the bus.
+    pub fn advance(&self, duration: Duration) {
+        self.lock().advance(duration);
+    }
+
+    pub(crate) fn set_parameter(&self, address: u8, parameter: u16, value: i32) -> StatusCode {
+        let result = self
+            .lock()
+            .transaction(address, |drive| drive.write(parameter, value));
+
+        match result {
+            Ok(()) => StatusCode::Ok,
+            Err(e) => e,
+        }
+    }
+
+    pub(crate) fn read_parameter(&self, address: u8, parameter: u16) -> Result<i32, StatusCode> {
+        self.lock()
+            .transaction(address, |drive| drive.read(parameter))
+    }
+
+    pub(crate) fn execute(
+        &self,
+        address: u8,
+        ops: &[Op],
+    ) -> Result<Vec<Result<i32, StatusCode>>, StatusCode> {
+        let mut bus = self.lock();
+
+        let results = bus.transaction(address, |drive| {
+            Ok(ops
+                .iter()
+                .map(|op| match *op {
+                    Op::Read(parameter) => drive.read(parameter),
+                    Op::Write(parameter, value) => drive.write(parameter, value).map(|_| 0),
+                })
+                .collect::<Vec<_>>())
+        })?;
+
+        for result in &results {
+            if let Err(e) = result {
+                bus.cumulative_status |= e.bits() as u32;
+            }
+        }
+
+        Ok(results)
+    }
+
+    pub(crate) fn fast_update_cycle(
+        &self,
+        address: u8,
+        out1: u16,
+        out2: u16,
+    ) -> Result<(u16, u16), StatusCode> {
+        self.lock()
+            .transaction(address, |drive| drive.fast_update_cycle(out1, out2))
+    }
+
+    pub(crate) fn cumulative_status(&self) -> i32 {
+        self.lock().cumulative_status as i32
+    }
+
+    pub(crate) fn reset_cumulative_status(&self) -> StatusCode {
+        self.lock().cumulative_status = 0;
+
+        StatusCode::Ok
+    }
+}
+
+impl SimBus {
+    fn advance(&mut self, duration: Duration) {
+        for drive in self.drives.values_mut() {
+            drive.advance(duration);
+        }
+    }
+
+    fn transaction<T>(
+        &mut self,
+        address: u8,
+        f: impl FnOnce(&mut SimDrive) -> Result<T, StatusCode>,
+    ) -> Result<T, StatusCode> {
+        self.advance(self.transaction_time);
+
+        let result = if self.failures > 0 {
+            self.failures -= 1;
+
+            Err(StatusCode::ErrCommunication)
+        } else {
+            self.drives
+                .get_mut(&address)
+                .ok_or(StatusCode::ErrNodevice)
+                .and_then(|drive| {
+                    drive.since_packet = Duration::ZERO;
+
+                    f(drive)
+                })
+        };
+
+        self.cumulative_status |= match &result {
+            Ok(_) => StatusCode::Ok,
+            Err(e) => *e,
+        }
+        .bits() as u32;
+
+        result
+    }
+}
+
+/// Bus with a default drive at address 1, and a connection to it.
+#[cfg(test)]
+pub(crate) fn setup() -> (Sim, crate::Argon) {
+    let sim = Sim::new();
+    sim.add_drive(1, SimDrive::new());
+
+    let argon = crate::Argon::with_sim(&sim, 1).unwrap();
+
+    (sim, argon)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ControlMode;
+
+    #[test]
+    fn velocity() {
+        let (sim, mut argon) = setup();
+
+        argon.set_control_mode(ControlMode::Velocity).unwrap();
+        argon.set_velocity_rps(10.0).unwrap();
+
+        assert!((argon.setpoint_rps().unwrap() - 10.0).abs() < 0.01);
+        assert!(!argon.status().unwrap().standing_still());
+
+        sim.advance(Duration::from_millis(500));
+
+        assert!((argon.velocity_rps().unwrap() - 10.0).abs() < 0.5);
+
+        argon.set_velocity_rps(0.0).unwrap();
+        sim.advance(Duration::from_millis(500));
+
+        assert_eq!(argon.velocity_rps().unwrap(), 0.0);
+        assert!(argon.status().unwrap().standing_still());
+    }
+}
@@ -1,51 +1,78 @@
-use simplemotion_sys::{
-    STAT_BRAKING, STAT_ENABLED, STAT_FAULTSTOP, STAT_FERROR_RECOVERY, STAT_FERROR_WARNING,
-    STAT_HOMING, STAT_INITIALIZED, STAT_PERMANENT_STOP, STAT_QUICK_STOP_ACTIVE, STAT_RUN,
-    STAT_SAFE_TORQUE_MODE_ACTIVE, STAT_SERVO_READY, STAT_STANDBY, STAT_STANDING_STILL,
-    STAT_STO_ACTIVE, STAT_TARGET_REACHED, STAT_VOLTAGES_OK,
+use crate::{
+    defs::{
+        STAT_BRAKING, STAT_ENABLED, STAT_FAULTSTOP, STAT_FERROR_RECOVERY, STAT_FERROR_WARNING,
+        STAT_HOMING, STAT_INITIALIZED, STAT_PERMANENT_STOP, STAT_QUICK_STOP_ACTIVE, STAT_RUN,
+        STAT_SAFE_TORQUE_MODE_ACTIVE, STAT_SERVO_READY, STAT_STANDBY, STAT_STANDING_STILL,
+        STAT_STO_ACTIVE, STAT_TARGET_REACHED, STAT_VOLTAGES_OK,
+    },
+    faults::write_flags,
 };
+use std::fmt;
 
-#[derive(Debug)]
-pub struct Status {
-    pub target_reached: bool,
-    pub ferror_recovery: bool,
-    pub run: bool,
-    pub enabled: bool,
-    pub faultstop: bool,
-    pub ferror_warning: bool,
-    pub sto_active: bool,
-    pub servo_ready: bool,
-    pub braking: bool,
-    pub homing: bool,
-    pub initialized: bool,
-    pub voltages_ok: bool,
-    pub permanent_stop: bool,
-    pub standing_still: bool,
-    pub quick_stop_active: bool,
-    pub safe_torque_mode_active: bool,
-    pub standby: bool,
+bitflags::bitflags! {
+    /// Drive status, read from `SMP_STATUS`.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct Status: u32 {
+        const TARGET_REACHED = STAT_TARGET_REACHED;
+        const FERROR_RECOVERY = STAT_FERROR_RECOVERY;
+        const RUN = STAT_RUN;
+        const ENABLED = STAT_ENABLED;
+        const FAULTSTOP = STAT_FAULTSTOP;
+        const FERROR_WARNING = STAT_FERROR_WARNING;
+        const STO_ACTIVE = STAT_STO_ACTIVE;
+        const SERVO_READY = STAT_SERVO_READY;
+        const BRAKING = STAT_BRAKING;
+        const HOMING = STAT_HOMING;
+        const INITIALIZED = STAT_INITIALIZED;
+        const VOLTAGES_OK = STAT_VOLTAGES_OK;
+        const PERMANENT_STOP = STAT_PERMANENT_STOP;
+        const STANDING_STILL = STAT_STANDING_STILL;
+        const QUICK_STOP_ACTIVE = STAT_QUICK_STOP_ACTIVE;
+        const SAFE_TORQUE_MODE_ACTIVE = STAT_SAFE_TORQUE_MODE_ACTIVE;
+        const STANDBY = STAT_STANDBY;
+    }
+}
+
+impl Status {
+    flag_accessors! {
+        target_reached => TARGET_REACHED,
+        ferror_recovery => FERROR_RECOVERY,
+        run => RUN,
+        enabled => ENABLED,
+        faultstop => FAULTSTOP,
+        ferror_warning => FERROR_WARNING,
+        sto_active => STO_ACTIVE,
+        servo_ready => SERVO_READY,
+        braking => BRAKING,
+        homing => HOMING,
+        initialized => INITIALIZED,
+        voltages_ok => VOLTAGES_OK,
+        permanent_stop => PERMANENT_STOP,
+        standing_still => STANDING_STILL,
+        quick_stop_active => QUICK_STOP_ACTIVE,
+        safe_torque_mode_active => SAFE_TORQUE_MODE_ACTIVE,
+        standby => STANDBY,
+    }
+
+    /// Raw `SMP_STATUS` value, including any bits without a name.
+    pub fn to_bits(&self) -> u32 {
+        self.bits()
+    }
 }
 
 impl From<u32> for Status {
     fn from(other: u32) -> Self {
-        Self {
-            target_reached: other & STAT_TARGET_REACHED > 0,
-            ferror_recovery: other & STAT_FERROR_RECOVERY > 0,
-            run: other & STAT_RUN > 0,
-            enabled: other & STAT_ENABLED > 0,
-            faultstop: other & STAT_FAULTSTOP > 0,
-            ferror_warning: other & STAT_FERROR_WARNING > 0,
-            sto_active: other & STAT_STO_ACTIVE > 0,
-            servo_ready: other & STAT_SERVO_READY > 0,
-            braking: other & STAT_BRAKING > 0,
-            homing: other & STAT_HOMING > 0,
-            initialized: other & STAT_INITIALIZED > 0,
-            voltages_ok: other & STAT_VOLTAGES_OK > 0,
-            permanent_stop: other & STAT_PERMANENT_STOP > 0,
-            standing_still: other & STAT_STANDING_STILL > 0,
-            quick_stop_active: other & STAT_QUICK_STOP_ACTIVE > 0,
-            safe_torque_mode_active: other & STAT_SAFE_TORQUE_MODE_ACTIVE > 0,
-            standby: other & STAT_STANDBY > 0,
-        }
+        Self::from_bits_retain(other)
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_flags(
+            f,
+            self.iter_names().map(|(name, _)| name),
+            self.bits() & !Self::all().bits(),
+        )
     }
 }
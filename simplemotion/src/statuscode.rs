@@ -1,64 +1,214 @@
-use simplemotion_sys::{
-    SM_ERR_BUS, SM_ERR_COMMUNICATION, SM_ERR_LENGTH, SM_ERR_NODEVICE, SM_ERR_PARAMETER, SM_NONE,
-    SM_OK,
-};
-
-/// Status.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub enum StatusCode {
-    None = SM_NONE as isize,
-    Ok = SM_OK as isize,
-    ErrNodevice = SM_ERR_NODEVICE as isize,
-    ErrBus = SM_ERR_BUS as isize,
-    ErrCommunication = SM_ERR_COMMUNICATION as isize,
-    ErrParameter = SM_ERR_PARAMETER as isize,
-    ErrLength = SM_ERR_LENGTH as isize,
-    Unknown = 999,
+use std::fmt;
+
+pub use flags::StatusCode;
+
+mod flags {
+    // Names match the variants of the enum this replaced
+    #![allow(non_upper_case_globals)]
+
+    use crate::defs::{
+        SM_ERR_BUS, SM_ERR_COMMUNICATION, SM_ERR_LENGTH, SM_ERR_NODEVICE, SM_ERR_PARAMETER, SM_OK,
+    };
+
+    bitflags::bitflags! {
+        /// SimpleMotion return status.
+        ///
+        /// Return values from the SimpleMotion library are bitfields: the cumulative bus status
+        /// and failed batch transactions can have several error bits set at once. Bits without a
+        /// name are kept, so the raw value returned by the library is always available from
+        /// [`StatusCode::bits`].
+        ///
+        /// A negative value is a failed bus open, where the library returns an invalid handle
+        /// instead of a status. No status at all is [`StatusCode::empty`].
+        ///
+        /// ```rust
+        /// use simplemotion::StatusCode;
+        ///
+        /// let status =
+        ///     StatusCode::from(StatusCode::ErrNodevice.bits() | StatusCode::ErrCommunication.bits());
+        ///
+        /// assert!(status.is_err());
+        /// assert!(status.contains(StatusCode::ErrCommunication));
+        /// assert!(!status.contains(StatusCode::ErrBus));
+        /// ```
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+        pub struct StatusCode: i32 {
+            /// Success.
+            const Ok = SM_OK as i32;
+            /// The device didn't respond.
+            const ErrNodevice = SM_ERR_NODEVICE as i32;
+            /// The bus failed or isn't open.
+            const ErrBus = SM_ERR_BUS as i32;
+            /// A reply was lost or corrupted.
+            const ErrCommunication = SM_ERR_COMMUNICATION as i32;
+            /// The device rejected a parameter address or value.
+            const ErrParameter = SM_ERR_PARAMETER as i32;
+            /// A payload was too long or a reply had an unexpected length.
+            const ErrLength = SM_ERR_LENGTH as i32;
+        }
+    }
 }
 
 impl StatusCode {
+    /// All error bits.
+    pub(crate) const ERRORS: Self = Self::ErrNodevice
+        .union(Self::ErrBus)
+        .union(Self::ErrCommunication)
+        .union(Self::ErrParameter)
+        .union(Self::ErrLength);
+
+    /// Success, with no error bits set.
     pub fn is_ok(&self) -> bool {
-        *self == Self::Ok
+        !self.is_open_failure() && self.contains(Self::Ok) && !self.intersects(Self::ERRORS)
     }
 
     pub fn is_err(&self) -> bool {
         !self.is_ok()
     }
+
+    /// The bus couldn't be opened. The raw value is the invalid handle returned by the library.
+    pub fn is_open_failure(&self) -> bool {
+        self.bits() < 0
+    }
+
+    /// The drive didn't respond, or its reply was lost or corrupted.
+    pub(crate) fn is_comms_error(&self) -> bool {
+        self.is_open_failure()
+            || self.intersects(Self::ErrNodevice | Self::ErrBus | Self::ErrCommunication)
+    }
+
+    /// Bits set that have no name.
+    fn unknown_bits(&self) -> i32 {
+        self.bits() & !Self::all().bits()
+    }
+}
+
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_open_failure() {
+            return write!(f, "bus could not be opened (handle {})", self.bits());
+        }
+
+        let mut reasons = Vec::new();
+
+        if self.contains(Self::ErrNodevice) {
+            reasons.push("device did not respond".to_string());
+        }
+
+        if self.contains(Self::ErrBus) {
+            reasons.push("bus error or bus not open".to_string());
+        }
+
+        if self.contains(Self::ErrCommunication) {
+            reasons.push("reply lost or corrupted".to_string());
+        }
+
+        if self.contains(Self::ErrParameter) {
+            reasons.push("invalid parameter address or value".to_string());
+        }
+
+        if self.contains(Self::ErrLength) {
+            reasons.push("invalid payload length".to_string());
+        }
+
+        if self.unknown_bits() != 0 {
+            reasons.push(format!("unknown bits {:#x}", self.unknown_bits()));
+        }
+
+        if reasons.is_empty() {
+            if self.contains(Self::Ok) {
+                reasons.push("ok".to_string());
+            } else {
+                reasons.push("no status".to_string());
+            }
+        }
+
+        write!(f, "{} (raw {})", reasons.join(", "), self.bits())
+    }
 }
 
 impl From<u32> for StatusCode {
     fn from(value: u32) -> Self {
-        match value {
-            SM_NONE => Self::None,
-            SM_OK => Self::Ok,
-            SM_ERR_NODEVICE => Self::ErrNodevice,
-            SM_ERR_BUS => Self::ErrBus,
-            SM_ERR_COMMUNICATION => Self::ErrCommunication,
-            SM_ERR_PARAMETER => Self::ErrParameter,
-            SM_ERR_LENGTH => Self::ErrLength,
-            _ => Self::Unknown,
-        }
+        Self::from_bits_retain(value as i32)
     }
 }
 
 impl From<i64> for StatusCode {
     fn from(value: i64) -> Self {
-        (value as u32).into()
+        Self::from_bits_retain(value as i32)
     }
 }
 
 impl From<i32> for StatusCode {
     fn from(value: i32) -> Self {
-        if value < 0 {
-            StatusCode::Unknown
-        } else {
-            (value as u32).into()
-        }
+        Self::from_bits_retain(value)
     }
 }
 
 impl PartialEq<i32> for StatusCode {
     fn eq(&self, other: &i32) -> bool {
-        *self as i32 == *other
+        self.bits() == *other
+    }
+}
+
+/// Serialized as the raw value, so negative bus open handles survive a round trip.
+#[cfg(feature = "serde")]
+impl serde::Serialize for StatusCode {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i32(self.bits())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for StatusCode {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        i32::deserialize(deserializer).map(Self::from_bits_retain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::defs::{SM_ERR_COMMUNICATION, SM_ERR_NODEVICE, SM_ERR_PARAMETER, SM_NONE, SM_OK};
+
+    #[test]
+    fn decode_bits() {
+        let ok = StatusCode::from(SM_OK);
+
+        assert!(ok.is_ok());
+        assert_eq!(ok.to_string(), "ok (raw 1)");
+
+        let status = StatusCode::from(SM_OK | SM_ERR_NODEVICE | SM_ERR_COMMUNICATION | 0x100);
+
+        assert!(status.is_err());
+        assert!(status.is_comms_error());
+        assert!(status.contains(StatusCode::ErrNodevice | StatusCode::ErrCommunication));
+        assert_eq!(status.bits(), 0x10b);
+        assert_eq!(
+            status.to_string(),
+            "device did not respond, reply lost or corrupted, unknown bits 0x100 (raw 267)"
+        );
+
+        let parameter = StatusCode::from(SM_ERR_PARAMETER);
+
+        assert!(parameter.is_err());
+        assert!(!parameter.is_comms_error());
+
+        let none = StatusCode::from(SM_NONE);
+
+        assert_eq!(none, StatusCode::empty());
+        assert!(none.is_err());
+        assert_eq!(none.to_string(), "no status (raw 0)");
+    }
+
+    #[test]
+    fn open_failure() {
+        let status = StatusCode::from(-1i64);
+
+        assert!(status.is_open_failure());
+        assert!(status.is_err());
+        assert!(status.is_comms_error());
+        assert_eq!(status, -1);
+        assert_eq!(status.to_string(), "bus could not be opened (handle -1)");
     }
 }
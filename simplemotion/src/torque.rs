@@ -0,0 +1,99 @@
+//! Torque and motor current.
+
+use crate::Error;
+
+/// A torque, given either as motor current or as torque at the motor shaft.
+///
+/// The drive controls motor current. Torque in newton metres is converted to current with the
+/// motor torque constant set by [`Argon::set_torque_constant`](crate::Argon::set_torque_constant).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Torque {
+    /// Motor current in amps.
+    Amps(f64),
+
+    /// Torque in newton metres.
+    NewtonMetres(f64),
+}
+
+impl Torque {
+    /// Convert to motor current in milliamps, the unit used by the drive.
+    ///
+    /// `torque_constant` is in Nm/A.
+    pub(crate) fn to_milliamps(self, torque_constant: Option<f64>) -> Result<f64, Error> {
+        match self {
+            Self::Amps(amps) => Ok(amps * 1000.0),
+            Self::NewtonMetres(nm) => torque_constant
+                .map(|constant| nm / constant * 1000.0)
+                .ok_or(Error::NoTorqueConstant),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        defs::{SMP_ABSOLUTE_SETPOINT, SMP_INPUT_DIVIDER, SMP_INPUT_MULTIPLIER},
+        sim::{setup, Sim, SimDrive},
+        Argon, ControlMode,
+    };
+
+    #[test]
+    fn milliamps() {
+        assert_eq!(Torque::Amps(1.5).to_milliamps(None).unwrap(), 1500.0);
+        assert_eq!(
+            Torque::NewtonMetres(0.5).to_milliamps(Some(0.25)).unwrap(),
+            2000.0
+        );
+        assert!(matches!(
+            Torque::NewtonMetres(0.5).to_milliamps(None),
+            Err(Error::NoTorqueConstant)
+        ));
+    }
+
+    #[test]
+    fn torque() {
+        let (_sim, mut argon) = setup();
+
+        argon.set_control_mode(ControlMode::Torque).unwrap();
+
+        assert!(matches!(
+            argon.set_torque_setpoint(Torque::NewtonMetres(2.0)),
+            Err(Error::NoTorqueConstant)
+        ));
+
+        argon.set_torque_constant(0.5);
+        argon
+            .set_torque_setpoint(Torque::NewtonMetres(2.0))
+            .unwrap();
+
+        assert!((argon.actual_current().unwrap() - 4.0).abs() < 0.01);
+        assert!((argon.actual_torque().unwrap() - 2.0).abs() < 0.01);
+
+        argon.set_peak_torque_limit(Torque::Amps(3.0)).unwrap();
+
+        assert!((argon.actual_torque().unwrap() - 1.5).abs() < 0.01);
+    }
+
+    /// `[MUL]/[DIV]` only scales position and velocity setpoints.
+    #[test]
+    fn unscaled() {
+        let sim = Sim::new();
+        sim.add_drive(
+            1,
+            SimDrive::new()
+                .with_parameter(SMP_INPUT_MULTIPLIER as u16, 2)
+                .with_parameter(SMP_INPUT_DIVIDER as u16, 5),
+        );
+
+        let argon = Argon::with_sim(&sim, 1).unwrap();
+
+        argon.set_control_mode(ControlMode::Torque).unwrap();
+        argon.set_torque_setpoint(Torque::Amps(1.5)).unwrap();
+
+        let setpoint = sim.with_drive(1, |drive| drive.parameter(SMP_ABSOLUTE_SETPOINT));
+
+        assert_eq!(setpoint, Some(1500));
+        assert!((argon.actual_current().unwrap() - 1.5).abs() < 0.01);
+    }
+}
@@ -0,0 +1,145 @@
+//! Mechanical transmission between the motor and the driven axis.
+
+use crate::Error;
+
+/// Mechanics between the motor shaft and the output, e.g. a belt reduction to a spindle or a ball
+/// screw on a linear axis.
+///
+/// Set with [`Argon::set_transmission`](crate::Argon::set_transmission), after which positions
+/// and velocities given to or returned from [`Argon`](crate::Argon) are those of the output
+/// rather than the motor.
+///
+/// ```rust
+/// use simplemotion::Transmission;
+///
+/// // 3:1 belt reduction onto a 5mm pitch ball screw, with the motor mounted backwards
+/// let transmission = Transmission::new()
+///     .with_gear_ratio(3.0)?
+///     .with_screw_pitch(5.0)
+///     .with_inverted(true);
+/// # Ok::<(), simplemotion::Error>(())
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transmission {
+    /// Motor revolutions per output revolution.
+    gear_ratio: f64,
+
+    /// Linear travel per output revolution in mm.
+    screw_pitch: Option<f64>,
+
+    /// Output turns the opposite way to the motor.
+    inverted: bool,
+}
+
+impl Default for Transmission {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transmission {
+    /// A direct drive: the output is the motor shaft.
+    pub fn new() -> Self {
+        Self {
+            gear_ratio: 1.0,
+            screw_pitch: None,
+            inverted: false,
+        }
+    }
+
+    /// Set the number of motor revolutions per output revolution, e.g. `3.0` for a 3:1
+    /// reduction.
+    ///
+    /// Fails with [`Error::InvalidGearRatio`] unless the ratio is finite and positive. Use
+    /// [`Transmission::with_inverted`] for an output that turns the other way.
+    pub fn with_gear_ratio(mut self, gear_ratio: f64) -> Result<Self, Error> {
+        if !(gear_ratio.is_finite() && gear_ratio > 0.0) {
+            return Err(Error::InvalidGearRatio(gear_ratio));
+        }
+
+        self.gear_ratio = gear_ratio;
+
+        Ok(self)
+    }
+
+    /// Set the linear travel per output revolution in mm, e.g. the pitch of a lead screw.
+    /// Required for conversions to and from millimetres.
+    pub fn with_screw_pitch(mut self, mm_per_rev: f64) -> Self {
+        self.screw_pitch = Some(mm_per_rev);
+
+        self
+    }
+
+    /// Set whether the output turns the opposite way to the motor.
+    pub fn with_inverted(mut self, inverted: bool) -> Self {
+        self.inverted = inverted;
+
+        self
+    }
+
+    /// Motor revolutions per output revolution.
+    pub fn gear_ratio(&self) -> f64 {
+        self.gear_ratio
+    }
+
+    /// Linear travel per output revolution in mm, if set.
+    pub fn screw_pitch(&self) -> Option<f64> {
+        self.screw_pitch
+    }
+
+    /// Whether the output turns the opposite way to the motor.
+    pub fn inverted(&self) -> bool {
+        self.inverted
+    }
+
+    /// Signed motor revolutions per output revolution.
+    pub(crate) fn motor_revs_per_rev(&self) -> f64 {
+        if self.inverted {
+            -self.gear_ratio
+        } else {
+            self.gear_ratio
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sim::setup, ControlMode};
+    use std::time::Duration;
+
+    #[test]
+    fn transmission() {
+        let (sim, mut argon) = setup();
+        sim.set_transaction_time(Duration::from_millis(10));
+
+        argon.set_transmission(
+            Transmission::new()
+                .with_gear_ratio(2.0)
+                .unwrap()
+                .with_inverted(true),
+        );
+        argon.set_control_mode(ControlMode::Position).unwrap();
+
+        argon.move_absolute(90.0).unwrap();
+        argon
+            .wait_until_target_reached(Duration::from_secs(5))
+            .unwrap();
+
+        // Half a motor revolution backwards
+        let position = sim.with_drive(1, |drive| drive.position()).unwrap();
+
+        assert!((position + 4000.0).abs() < 5.0);
+        assert!((argon.position_degrees().unwrap() - 90.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn invalid_gear_ratio() {
+        for ratio in [0.0, -2.0, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                Transmission::new().with_gear_ratio(ratio),
+                Err(Error::InvalidGearRatio(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Byte stream transports for the SimpleMotion bus.
+
+use std::{
+    io,
+    net::TcpStream,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// SimpleMotion default bus baud rate.
+#[cfg(feature = "pure-rust")]
+pub(crate) const DEFAULT_BAUDRATE: u32 = 460_800;
+
+/// Reply timeout. Matches the 100ms passed to `smSetTimeout` when opening a device bus.
+pub(crate) const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A byte stream that carries SimpleMotion packets, e.g. a serial port, TCP socket or an in-memory
+/// pipe.
+///
+/// Use with [`Argon::with_transport`](crate::Argon::with_transport).
+pub trait Transport: Send {
+    /// Read available bytes into `buf`, returning how many were read.
+    ///
+    /// Should block for at most the duration given to [`set_timeout`](Transport::set_timeout) and
+    /// return an error of kind [`io::ErrorKind::TimedOut`] if nothing was received.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>;
+
+    /// Write bytes from `buf`, returning how many were written.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>;
+
+    /// Make sure all written bytes are sent.
+    fn flush(&mut self) -> io::Result<()>;
+
+    /// Set the read timeout.
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+
+    /// Set the bus baud rate. Transports without a baud rate should ignore this.
+    fn set_baudrate(&mut self, baudrate: u32) -> io::Result<()>;
+
+    /// Discard any received but unread bytes.
+    ///
+    /// The default implementation does nothing.
+    fn purge(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for TcpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Unix reports a read timeout as `WouldBlock`
+        io::Read::read(self, buf).map_err(|e| match e.kind() {
+            io::ErrorKind::WouldBlock => io::ErrorKind::TimedOut.into(),
+            _ => e,
+        })
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::Write::write(self, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::Write::flush(self)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.set_read_timeout(Some(timeout))
+    }
+
+    fn set_baudrate(&mut self, _baudrate: u32) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "pure-rust")]
+impl Transport for Box<dyn serialport::SerialPort> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        io::Read::read(self, buf)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::Write::write(self, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::Write::flush(self)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        serialport::SerialPort::set_timeout(self.as_mut(), timeout).map_err(io::Error::from)
+    }
+
+    fn set_baudrate(&mut self, baudrate: u32) -> io::Result<()> {
+        self.set_baud_rate(baudrate).map_err(io::Error::from)
+    }
+
+    fn purge(&mut self) -> io::Result<()> {
+        self.clear(serialport::ClearBuffer::Input)
+            .map_err(io::Error::from)
+    }
+}
+
+/// A transport shared between a bus handle and the C library callbacks.
+pub(crate) type SharedTransport = Arc<Mutex<dyn Transport>>;
+
+/// Where a bus handle gets its byte stream from, kept so the bus can be reopened.
+#[derive(Clone)]
+pub(crate) enum Source {
+    /// Device path, e.g. `/dev/ttyUSB0`.
+    Device(String),
+
+    /// User provided transport.
+    Transport(SharedTransport),
+}
+
+impl std::fmt::Debug for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Device(device) => f.debug_tuple("Device").field(device).finish(),
+            Self::Transport(_) => f.write_str("Transport"),
+        }
+    }
+}
+
+/// [`io::Read`]/[`io::Write`] adapter over a [`Transport`].
+#[cfg(feature = "pure-rust")]
+pub(crate) struct Io<'a>(pub &'a mut dyn Transport);
+
+#[cfg(feature = "pure-rust")]
+impl io::Read for Io<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+#[cfg(feature = "pure-rust")]
+impl io::Write for Io<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn tcp_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let _peer = listener.accept().unwrap();
+
+        Transport::set_timeout(&mut stream, Duration::from_millis(10)).unwrap();
+
+        assert_eq!(
+            Transport::read(&mut stream, &mut [0; 4])
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::TimedOut
+        );
+    }
+}
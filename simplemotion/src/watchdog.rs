@@ -0,0 +1,235 @@
+//! Host communication watchdog.
+
+use crate::{lock, parameters::Parameter, Bus, Error};
+use std::{
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Faults the drive if the host stops talking to it.
+///
+/// Created by [`Argon::watchdog`](crate::Argon::watchdog), which sets the drive's communication
+/// timeout. If no packet reaches the drive within the timeout, it stops the motor and raises
+/// [`Faults::host_comm_error`](crate::Faults::host_comm_error).
+///
+/// A background thread keeps the drive alive while the host is idle, e.g. while waiting for a
+/// move to complete, but only as long as [`Watchdog::feed`] is called at least once per timeout.
+/// Call it from the control loop so that a hung loop, or a host process that dies, lets the
+/// drive time out.
+///
+/// Dropping the `Watchdog` disables the drive's communication timeout.
+///
+/// ```rust,no_run
+/// use simplemotion::Argon;
+/// use std::time::Duration;
+///
+/// let argon = Argon::connect("/dev/ttyUSB0", 1)?;
+///
+/// let watchdog = argon.watchdog(Duration::from_millis(200))?;
+///
+/// loop {
+///     watchdog.feed();
+///
+///     // Control loop
+///     argon.status()?;
+///
+///     std::thread::sleep(Duration::from_millis(10));
+/// }
+/// # Ok::<(), simplemotion::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Watchdog {
+    bus: Bus,
+    address: u8,
+    last_feed: Arc<Mutex<Instant>>,
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    pub(crate) fn start(bus: Bus, address: u8, timeout: Duration) -> Result<Self, Error> {
+        let millis = timeout.as_millis().clamp(1, i32::MAX as u128) as i32;
+
+        let result = bus
+            .handle()
+            .set_parameter(address, Parameter::WatchdogTimeout as u16, millis);
+
+        if result.is_err() {
+            return Err(Error::SetParameter(
+                Parameter::WatchdogTimeout,
+                millis,
+                result,
+            ));
+        }
+
+        let last_feed = Arc::new(Mutex::new(Instant::now()));
+        let (stop, stopped) = mpsc::channel();
+
+        let thread = {
+            let keepalive = Keepalive {
+                bus: bus.clone(),
+                address,
+                timeout,
+                last_feed: last_feed.clone(),
+            };
+
+            thread::Builder::new()
+                .name(format!("simplemotion-watchdog-{}", address))
+                .spawn(move || keepalive.run(stopped))
+                .expect("Failed to spawn watchdog thread")
+        };
+
+        Ok(Self {
+            bus,
+            address,
+            last_feed,
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+
+    /// Signal that the host is alive.
+    ///
+    /// Keepalive packets are only sent within one timeout of the last call.
+    pub fn feed(&self) {
+        *lock(&self.last_feed) = Instant::now();
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        // Disconnecting the channel stops the thread
+        self.stop.take();
+
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("Watchdog thread panicked");
+            }
+        }
+
+        let result =
+            self.bus
+                .handle()
+                .set_parameter(self.address, Parameter::WatchdogTimeout as u16, 0);
+
+        if result.is_err() {
+            log::error!("Failed to disable watchdog: {}", result);
+        }
+    }
+}
+
+/// Keeps the drive alive from the watchdog thread.
+struct Keepalive {
+    bus: Bus,
+    address: u8,
+    timeout: Duration,
+    last_feed: Arc<Mutex<Instant>>,
+}
+
+impl Keepalive {
+    fn run(self, stopped: mpsc::Receiver<()>) {
+        let interval = (self.timeout / 4).max(Duration::from_millis(1));
+
+        // Stops when the `Watchdog` is dropped
+        while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+            self.tick(Instant::now());
+        }
+
+        log::debug!("Watchdog thread for drive {} stopped", self.address);
+    }
+
+    /// Send a keepalive if the watchdog was fed within one timeout of `now`.
+    fn tick(&self, now: Instant) {
+        let fed = now.saturating_duration_since(*lock(&self.last_feed)) < self.timeout;
+
+        if !fed {
+            log::trace!(
+                "Watchdog for drive {} not fed, skipping keepalive",
+                self.address
+            );
+
+            return;
+        }
+
+        if let Err(code) = self
+            .bus
+            .handle()
+            .read_parameter(self.address, Parameter::Status as u16)
+        {
+            log::warn!(
+                "Watchdog keepalive to drive {} failed: {}",
+                self.address,
+                code
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{registry::Timeout, sim::setup};
+
+    #[test]
+    fn keepalive() {
+        let (sim, argon) = setup();
+
+        argon.set::<Timeout>(100).unwrap();
+
+        let fed = Instant::now();
+        let keepalive = Keepalive {
+            bus: argon.bus.clone(),
+            address: 1,
+            timeout: Duration::from_millis(100),
+            last_feed: Arc::new(Mutex::new(fed)),
+        };
+
+        // A keepalive between the two steps stops the drive timing out
+        sim.advance(Duration::from_millis(80));
+        keepalive.tick(fed + Duration::from_millis(50));
+        sim.advance(Duration::from_millis(80));
+
+        assert!(!argon.faults().unwrap().host_comm_error());
+
+        // Not fed within the timeout, so nothing is sent and the drive times out
+        sim.advance(Duration::from_millis(80));
+        keepalive.tick(fed + Duration::from_millis(150));
+        sim.advance(Duration::from_millis(80));
+
+        assert!(argon.faults().unwrap().host_comm_error());
+    }
+
+    #[test]
+    fn watchdog() {
+        let (sim, argon) = setup();
+
+        let watchdog = argon.watchdog(Duration::from_millis(100)).unwrap();
+
+        assert_eq!(argon.get::<Timeout>().unwrap(), 100);
+
+        sim.advance(Duration::from_millis(80));
+
+        // Wait for the thread to send a keepalive
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        while sim.with_drive(1, |drive| drive.since_packet()).unwrap() >= Duration::from_millis(80)
+        {
+            assert!(Instant::now() < deadline, "No keepalive sent");
+
+            watchdog.feed();
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        sim.advance(Duration::from_millis(80));
+
+        assert!(!argon.faults().unwrap().host_comm_error());
+
+        drop(watchdog);
+
+        assert_eq!(argon.get::<Timeout>().unwrap(), 0);
+    }
+}